@@ -5,6 +5,10 @@ use std::cmp::Ordering;
 use std::collections::VecDeque;
 use std::ops::Deref;
 
+mod encode;
+#[cfg(feature = "serde")]
+mod serde_impl;
+
 // Node link, e.g. nullable pointer, pattern, similar to the list.
 type Link<T> = Option<Box<Node<T>>>;
 
@@ -56,6 +60,18 @@ impl<T: Ord> Tree<T> {
         Self::default()
     }
 
+    /// Builds a perfectly balanced tree out of the strictly ascending
+    /// `items` in O(n).  Returns `None` if `items` is not strictly
+    /// ascending.
+    pub fn from_sorted(items: Vec<T>) -> Option<Self> {
+        if items.windows(2).any(|pair| pair[0] >= pair[1]) {
+            return None;
+        }
+        let len = items.len();
+        let root = build_balanced(&mut items.into_iter(), len);
+        Some(Self { root })
+    }
+
     pub fn iter(&self) -> Iter<T> {
         let mut deque = VecDeque::new();
         if let Some(root) = self.root.as_ref() {
@@ -83,6 +99,18 @@ impl<T: Ord> Tree<T> {
     }
 }
 
+// Builds the balanced subtree of the next `len` items, left subtree
+// first so that the items are consumed in order.
+fn build_balanced<T, I: Iterator<Item = T>>(items: &mut I, len: usize) -> Link<T> {
+    if len == 0 {
+        return None;
+    }
+    let left = build_balanced(items, len / 2);
+    let data = items.next()?;
+    let right = build_balanced(items, len - len / 2 - 1);
+    Some(Box::new(Node { left, right, data }))
+}
+
 #[derive(Debug, PartialEq)]
 struct Node<T> {
     left: Link<T>,
@@ -157,7 +185,88 @@ impl<'a, T> DoubleEndedIterator for NodeIter<'a, T> {
 
 #[cfg(test)]
 mod tests {
-    use super::{Node, Tree};
+    use super::{Link, Node, Tree};
+    use std::io::{Read, Write};
+
+    // pre-order list of the data with the presence of both children.
+    fn shape<T: Ord + Copy>(tree: &Tree<T>) -> Vec<(T, bool, bool)> {
+        let mut shape = vec![];
+        let mut unvisited: Vec<&Link<T>> = vec![&tree.root];
+        while let Some(link) = unvisited.pop() {
+            if let Some(node) = link {
+                shape.push((node.data, node.left.is_some(), node.right.is_some()));
+                unvisited.push(&node.right);
+                unvisited.push(&node.left);
+            }
+        }
+        shape
+    }
+
+    fn encode(tree: &Tree<u32>) -> Vec<u8> {
+        let mut buf = vec![];
+        tree.encode(&mut buf, |data, w| w.write_all(&data.to_le_bytes()))
+            .unwrap();
+        buf
+    }
+
+    fn decode(buf: &[u8]) -> std::io::Result<Tree<u32>> {
+        Tree::decode(buf, |r| {
+            let mut data = [0u8; 4];
+            r.read_exact(&mut data)?;
+            Ok(u32::from_le_bytes(data))
+        })
+    }
+
+    #[test]
+    fn tree_from_sorted() {
+        let tree = Tree::from_sorted((1..=6).collect()).unwrap();
+        assert_eq!(
+            shape(&tree),
+            vec![
+                (4, true, true),
+                (2, true, true),
+                (1, false, false),
+                (3, false, false),
+                (6, true, false),
+                (5, false, false),
+            ],
+        );
+        assert!(tree.iter().copied().eq(1..=6));
+        assert!(Tree::from_sorted(vec![2, 1]).is_none());
+    }
+
+    #[test]
+    fn tree_encode_decode() {
+        let mut tree = Tree::new();
+        for data in [50, 20, 80, 10, 30, 25, 90, 85] {
+            tree.insert(data);
+        }
+        let buf = encode(&tree);
+        let decoded = decode(&buf).unwrap();
+        assert_eq!(shape(&decoded), shape(&tree));
+        assert_eq!(encode(&decoded), buf);
+        assert_eq!(encode(&Tree::new()), [0]);
+    }
+
+    #[test]
+    fn tree_decode_invalid() {
+        // out of order.
+        assert!(decode(&[1, 0b10, 2, 0, 0, 0, 0, 1, 0, 0, 0]).is_err());
+        // truncated.
+        assert!(decode(&[1, 0b11, 1, 0, 0, 0]).is_err());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn tree_serde_json() {
+        let tree = Tree::from_sorted(vec![1, 2, 3]).unwrap();
+        assert_eq!(serde_json::to_string(&tree).unwrap(), "[1,2,3]");
+        let tree: Tree<u32> = serde_json::from_str("[3,2,1,2]").unwrap();
+        assert_eq!(
+            shape(&tree),
+            vec![(2, true, true), (1, false, false), (3, false, false)]
+        );
+    }
 
     #[test]
     fn tree_next_back() {
@@ -0,0 +1,54 @@
+//! Generates the C header of the exported functions with the `header`
+//! feature, and links libsnappy unless it's built with the pure Rust
+//! backend.
+//!
+//! It links the system libsnappy: out of `SNAPPY_LIB_DIR` if set, or as
+//! found by pkg-config.  It falls back to the plain `-lsnappy` in case
+//! pkg-config doesn't know about it, as some distributions don't ship
+//! `snappy.pc`.
+use std::env;
+
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    #[cfg(feature = "header")]
+    header();
+    if env::var_os("CARGO_FEATURE_BACKEND_RUST").is_some() {
+        return;
+    }
+    system();
+}
+
+// generates OUT_DIR/include/ffi.h and exposes the directory to the
+// integration tests through FFI_INCLUDE_DIR.
+#[cfg(feature = "header")]
+fn header() {
+    use std::path::PathBuf;
+
+    let crate_dir = env::var("CARGO_MANIFEST_DIR").unwrap();
+    let include = PathBuf::from(env::var_os("OUT_DIR").unwrap()).join("include");
+    println!("cargo:rerun-if-changed=src/capi.rs");
+    println!("cargo:rerun-if-changed=cbindgen.toml");
+    let config = cbindgen::Config::from_file(PathBuf::from(&crate_dir).join("cbindgen.toml"))
+        .expect("cbindgen.toml");
+    // only the exported functions, not the imported ones in the other
+    // modules.
+    cbindgen::Builder::new()
+        .with_config(config)
+        .with_src(PathBuf::from(&crate_dir).join("src/capi.rs"))
+        .generate()
+        .expect("cbindgen")
+        .write_to_file(include.join("ffi.h"));
+    println!("cargo:rustc-env=FFI_INCLUDE_DIR={}", include.display());
+}
+
+fn system() {
+    println!("cargo:rerun-if-env-changed=SNAPPY_LIB_DIR");
+    if let Some(dir) = env::var_os("SNAPPY_LIB_DIR") {
+        println!("cargo:rustc-link-search=native={}", dir.to_string_lossy());
+        println!("cargo:rustc-link-lib=snappy");
+        return;
+    }
+    if pkg_config::probe_library("snappy").is_err() {
+        println!("cargo:rustc-link-lib=snappy");
+    }
+}
@@ -0,0 +1,180 @@
+//! C ABI of the Snappy functions, e.g. calling Rust from C.
+//!
+//! The functions mirror `snappy-c.h`, with the `ffi_` prefix not to
+//! clash with `libsnappy` itself.  The header is generated by cbindgen
+//! in `build.rs`.
+//!
+//! Panics never cross the boundary.  They are caught and reported as
+//! [`FfiStatus::Panic`], with the message available through
+//! [`ffi_last_panic_message`].
+use crate::SnappyError;
+use std::any::Any;
+use std::cell::RefCell;
+use std::ffi::CString;
+use std::os::raw::c_char;
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+use std::slice;
+
+/// Status returned by the `ffi_` functions.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FfiStatus {
+    Ok = 0,
+    InvalidInput = 1,
+    BufferTooSmall = 2,
+    /// Rust panicked, e.g. on the NULL pointer argument.
+    Panic = -1,
+}
+
+impl From<SnappyError> for FfiStatus {
+    fn from(e: SnappyError) -> Self {
+        match e {
+            SnappyError::InvalidInput | SnappyError::Unknown(_) => Self::InvalidInput,
+            SnappyError::BufferTooSmall => Self::BufferTooSmall,
+        }
+    }
+}
+
+thread_local! {
+    static LAST_PANIC: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+/// Returns the message of the last panic caught in the calling thread,
+/// or NULL if there is none.  It's valid until the next `ffi_` call in
+/// the same thread.
+#[no_mangle]
+pub extern "C" fn ffi_last_panic_message() -> *const c_char {
+    LAST_PANIC.with(|last| {
+        last.borrow()
+            .as_ref()
+            .map_or(ptr::null(), |msg| msg.as_ptr())
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn ffi_max_compressed_length(source_length: usize) -> usize {
+    crate::max_compressed_length(source_length)
+}
+
+/// # Safety
+///
+/// `input` should be valid for `input_length` bytes read, and
+/// `compressed` for `*compressed_length` bytes write.
+#[no_mangle]
+pub unsafe extern "C" fn ffi_compress(
+    input: *const u8,
+    input_length: usize,
+    compressed: *mut u8,
+    compressed_length: *mut usize,
+) -> FfiStatus {
+    guard(|| {
+        let len = out_length(compressed_length);
+        let src = input_slice(input, input_length);
+        let dst = output_slice(compressed, *len);
+        *len = crate::compress_into(src, dst)?;
+        Ok(())
+    })
+}
+
+/// # Safety
+///
+/// `compressed` should be valid for `compressed_length` bytes read, and
+/// `uncompressed` for `*uncompressed_length` bytes write.
+#[no_mangle]
+pub unsafe extern "C" fn ffi_uncompress(
+    compressed: *const u8,
+    compressed_length: usize,
+    uncompressed: *mut u8,
+    uncompressed_length: *mut usize,
+) -> FfiStatus {
+    guard(|| {
+        let len = out_length(uncompressed_length);
+        let src = input_slice(compressed, compressed_length);
+        let dst = output_slice(uncompressed, *len);
+        *len = crate::uncompress_into(src, dst)?;
+        Ok(())
+    })
+}
+
+/// # Safety
+///
+/// `compressed` should be valid for `compressed_length` bytes read.
+#[no_mangle]
+pub unsafe extern "C" fn ffi_uncompressed_length(
+    compressed: *const u8,
+    compressed_length: usize,
+    result: *mut usize,
+) -> FfiStatus {
+    guard(|| {
+        let result = out_length(result);
+        *result = crate::uncompressed_length(input_slice(compressed, compressed_length))?;
+        Ok(())
+    })
+}
+
+/// # Safety
+///
+/// `compressed` should be valid for `compressed_length` bytes read.
+#[no_mangle]
+pub unsafe extern "C" fn ffi_validate_compressed_buffer(
+    compressed: *const u8,
+    compressed_length: usize,
+) -> FfiStatus {
+    guard(|| {
+        let src = input_slice(compressed, compressed_length);
+        if crate::validate_compressed_buffer(src) {
+            Ok(())
+        } else {
+            Err(SnappyError::InvalidInput)
+        }
+    })
+}
+
+// runs f, catching the panic.
+fn guard<F>(f: F) -> FfiStatus
+where
+    F: FnOnce() -> Result<(), SnappyError>,
+{
+    LAST_PANIC.with(|last| last.borrow_mut().take());
+    match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(())) => FfiStatus::Ok,
+        Ok(Err(e)) => e.into(),
+        Err(payload) => {
+            let msg = panic_message(payload.as_ref());
+            LAST_PANIC.with(|last| *last.borrow_mut() = CString::new(msg).ok());
+            FfiStatus::Panic
+        }
+    }
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(msg) = payload.downcast_ref::<&str>() {
+        msg.to_string()
+    } else if let Some(msg) = payload.downcast_ref::<String>() {
+        msg.clone()
+    } else {
+        "unknown panic".to_string()
+    }
+}
+
+unsafe fn input_slice<'a>(data: *const u8, len: usize) -> &'a [u8] {
+    if len == 0 {
+        return &[];
+    }
+    assert!(!data.is_null(), "NULL input buffer");
+    slice::from_raw_parts(data, len)
+}
+
+unsafe fn output_slice<'a>(data: *mut u8, len: usize) -> &'a mut [u8] {
+    if len == 0 {
+        return &mut [];
+    }
+    assert!(!data.is_null(), "NULL output buffer");
+    slice::from_raw_parts_mut(data, len)
+}
+
+unsafe fn out_length<'a>(len: *mut usize) -> &'a mut usize {
+    assert!(!len.is_null(), "NULL length pointer");
+    &mut *len
+}
@@ -0,0 +1,156 @@
+//! Common interface of the Snappy and zlib codecs, to choose the codec
+//! at runtime.
+use crate::zlib::{self, ZlibReader, ZlibWriter};
+use crate::{SnappyReader, SnappyWriter};
+use std::io::{self, Read, Write};
+
+pub trait Codec {
+    fn name(&self) -> &'static str;
+
+    /// Compresses `src` as a whole.
+    fn compress(&self, src: &[u8]) -> io::Result<Vec<u8>>;
+
+    /// Uncompresses `src` compressed by [`Codec::compress`].
+    fn uncompress(&self, src: &[u8]) -> io::Result<Vec<u8>>;
+
+    /// Streaming compressor, which finishes the stream on drop.  Fails on
+    /// the invalid parameters, e.g. the zlib level.
+    fn writer<'a>(&self, inner: Box<dyn Write + 'a>) -> io::Result<Box<dyn Write + 'a>>;
+
+    /// Streaming uncompressor of the data written by [`Codec::writer`].
+    /// Fails if the uncompressor can't be initialized.
+    fn reader<'a>(&self, inner: Box<dyn Read + 'a>) -> io::Result<Box<dyn Read + 'a>>;
+}
+
+/// Returns the codec by name, e.g. `snappy` or `zlib`.
+pub fn by_name(name: &str) -> Option<Box<dyn Codec>> {
+    match name {
+        "snappy" => Some(Box::new(Snappy)),
+        "zlib" => Some(Box::new(Zlib::default())),
+        _ => None,
+    }
+}
+
+/// Snappy block format, and the framing format for the streams.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Snappy;
+
+impl Codec for Snappy {
+    fn name(&self) -> &'static str {
+        "snappy"
+    }
+
+    fn compress(&self, src: &[u8]) -> io::Result<Vec<u8>> {
+        Ok(crate::compress(src)?)
+    }
+
+    fn uncompress(&self, src: &[u8]) -> io::Result<Vec<u8>> {
+        Ok(crate::uncompress(src)?)
+    }
+
+    fn writer<'a>(&self, inner: Box<dyn Write + 'a>) -> io::Result<Box<dyn Write + 'a>> {
+        Ok(Box::new(SnappyWriter::new(inner)))
+    }
+
+    fn reader<'a>(&self, inner: Box<dyn Read + 'a>) -> io::Result<Box<dyn Read + 'a>> {
+        Ok(Box::new(SnappyReader::new(inner)))
+    }
+}
+
+/// zlib format, with the compression level.
+#[derive(Debug, Clone, Copy)]
+pub struct Zlib {
+    pub level: i32,
+}
+
+impl Default for Zlib {
+    fn default() -> Self {
+        Self {
+            level: zlib::DEFAULT_LEVEL,
+        }
+    }
+}
+
+impl Codec for Zlib {
+    fn name(&self) -> &'static str {
+        "zlib"
+    }
+
+    fn compress(&self, src: &[u8]) -> io::Result<Vec<u8>> {
+        Ok(zlib::compress(src, self.level)?)
+    }
+
+    fn uncompress(&self, src: &[u8]) -> io::Result<Vec<u8>> {
+        Ok(zlib::uncompress(src, zlib::MAX_UNCOMPRESSED_LEN)?)
+    }
+
+    fn writer<'a>(&self, inner: Box<dyn Write + 'a>) -> io::Result<Box<dyn Write + 'a>> {
+        Ok(Box::new(ZlibWriter::new(inner, self.level)?))
+    }
+
+    fn reader<'a>(&self, inner: Box<dyn Read + 'a>) -> io::Result<Box<dyn Read + 'a>> {
+        Ok(Box::new(ZlibReader::new(inner)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{by_name, Codec, Zlib};
+    use std::io::{Read, Write};
+
+    const NAMES: [&str; 2] = ["snappy", "zlib"];
+
+    fn data() -> Vec<u8> {
+        (0..200_000u32).map(|i| (i * 3 / 7 % 256) as u8).collect()
+    }
+
+    #[test]
+    fn round_trip() {
+        let d = data();
+        for name in NAMES {
+            let codec = by_name(name).unwrap();
+            assert_eq!(codec.name(), name);
+            let c = codec.compress(&d).unwrap();
+            assert!(c.len() < d.len(), "{name}");
+            assert_eq!(codec.uncompress(&c).unwrap(), d, "{name}");
+        }
+    }
+
+    #[test]
+    fn stream_round_trip() {
+        let d = data();
+        for name in NAMES {
+            let codec = by_name(name).unwrap();
+            let mut c = vec![];
+            {
+                let mut writer = codec.writer(Box::new(&mut c)).unwrap();
+                for chunk in d.chunks(7000) {
+                    writer.write_all(chunk).unwrap();
+                }
+            }
+            let mut out = vec![];
+            codec
+                .reader(Box::new(&c[..]))
+                .unwrap()
+                .read_to_end(&mut out)
+                .unwrap();
+            assert_eq!(out, d, "{name}");
+        }
+    }
+
+    #[test]
+    fn invalid() {
+        for name in NAMES {
+            let codec = by_name(name).unwrap();
+            assert!(codec.uncompress(b"\xff\xff\xff").is_err(), "{name}");
+        }
+        assert!(by_name("lz4").is_none());
+    }
+
+    #[test]
+    fn invalid_level() {
+        let codec = Zlib { level: 10 };
+        assert!(codec.compress(b"hello").is_err());
+        assert!(codec.writer(Box::new(vec![])).is_err());
+    }
+}
@@ -0,0 +1,32 @@
+//! Snappy error.
+use std::error::Error;
+use std::fmt::{self, Display};
+use std::io;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SnappyError {
+    /// `SNAPPY_INVALID_INPUT`, e.g. corrupted compressed data.
+    InvalidInput,
+    /// `SNAPPY_BUFFER_TOO_SMALL`, e.g. the output buffer is too small.
+    BufferTooSmall,
+    /// Status not defined in `snappy-c.h`.
+    Unknown(i32),
+}
+
+impl Error for SnappyError {}
+
+impl Display for SnappyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidInput => write!(f, "invalid snappy input"),
+            Self::BufferTooSmall => write!(f, "snappy buffer too small"),
+            Self::Unknown(status) => write!(f, "unknown snappy status {status}"),
+        }
+    }
+}
+
+impl From<SnappyError> for io::Error {
+    fn from(e: SnappyError) -> Self {
+        io::Error::new(io::ErrorKind::InvalidData, e)
+    }
+}
@@ -0,0 +1,410 @@
+//! Snappy [framing format] on top of the block [`compress`] and
+//! [`uncompress`], to stream the data larger than the memory.
+//!
+//! [framing format]: https://github.com/google/snappy/blob/main/framing_format.txt
+use super::{compress_into, max_compressed_length, uncompress_into, uncompressed_length};
+use std::io::{self, Read, Write};
+
+/// Maximum uncompressed data length of the single chunk.
+const MAX_BLOCK_LEN: usize = 65536;
+
+const STREAM_IDENTIFIER: &[u8] = b"sNaPpY";
+
+const CHUNK_COMPRESSED: u8 = 0x00;
+const CHUNK_UNCOMPRESSED: u8 = 0x01;
+const CHUNK_PADDING: u8 = 0xfe;
+const CHUNK_STREAM_IDENTIFIER: u8 = 0xff;
+
+/// Compresses the data written to it in the Snappy framing format.
+///
+/// The buffered data is written out as the chunk on [`Write::flush`]
+/// or on drop, just like the [`io::BufWriter`].
+pub struct SnappyWriter<W: Write> {
+    inner: Option<W>,
+    buf: Vec<u8>,
+    compressed: Vec<u8>,
+    header_written: bool,
+}
+
+impl<W: Write> Drop for SnappyWriter<W> {
+    fn drop(&mut self) {
+        if self.inner.is_some() {
+            // ignore the error, as in io::BufWriter.
+            let _ = self.write_chunk();
+        }
+    }
+}
+
+impl<W: Write> Write for SnappyWriter<W> {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        if self.buf.len() == MAX_BLOCK_LEN {
+            self.write_chunk()?;
+        }
+        let n = data.len().min(MAX_BLOCK_LEN - self.buf.len());
+        self.buf.extend_from_slice(&data[..n]);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.write_chunk()?;
+        self.get_mut().flush()
+    }
+}
+
+impl<W: Write> SnappyWriter<W> {
+    pub fn new(inner: W) -> Self {
+        Self {
+            inner: Some(inner),
+            buf: Vec::with_capacity(MAX_BLOCK_LEN),
+            compressed: vec![0; max_compressed_length(MAX_BLOCK_LEN)],
+            header_written: false,
+        }
+    }
+
+    pub fn get_ref(&self) -> &W {
+        self.inner
+            .as_ref()
+            .expect("SnappyWriter without inner writer")
+    }
+
+    pub fn get_mut(&mut self) -> &mut W {
+        self.inner
+            .as_mut()
+            .expect("SnappyWriter without inner writer")
+    }
+
+    /// Writes out the buffered data and returns the inner writer.
+    pub fn into_inner(mut self) -> io::Result<W> {
+        self.flush()?;
+        Ok(self
+            .inner
+            .take()
+            .expect("SnappyWriter without inner writer"))
+    }
+
+    fn write_chunk(&mut self) -> io::Result<()> {
+        if !self.header_written {
+            let inner = self
+                .inner
+                .as_mut()
+                .expect("SnappyWriter without inner writer");
+            write_chunk_header(inner, CHUNK_STREAM_IDENTIFIER, STREAM_IDENTIFIER.len())?;
+            inner.write_all(STREAM_IDENTIFIER)?;
+            self.header_written = true;
+        }
+        if self.buf.is_empty() {
+            return Ok(());
+        }
+        let checksum = masked_crc32c(&self.buf).to_le_bytes();
+        let len = compress_into(&self.buf, &mut self.compressed)?;
+        // store it uncompressed in case it doesn't compress well.
+        let (chunk_type, data) = if len < self.buf.len() - self.buf.len() / 8 {
+            (CHUNK_COMPRESSED, &self.compressed[..len])
+        } else {
+            (CHUNK_UNCOMPRESSED, &self.buf[..])
+        };
+        let inner = self
+            .inner
+            .as_mut()
+            .expect("SnappyWriter without inner writer");
+        write_chunk_header(inner, chunk_type, checksum.len() + data.len())?;
+        inner.write_all(&checksum)?;
+        inner.write_all(data)?;
+        self.buf.clear();
+        Ok(())
+    }
+}
+
+/// Uncompresses the Snappy framing format data read from the inner
+/// reader.
+pub struct SnappyReader<R: Read> {
+    inner: R,
+    // uncompressed data of the current chunk and the read position.
+    buf: Vec<u8>,
+    pos: usize,
+    // the next chunk, swapped into buf once it passes the checksum.
+    scratch: Vec<u8>,
+    header_read: bool,
+    // the corrupted or truncated stream, returned on every later read
+    // not to look like the end of the stream.
+    error: Option<(io::ErrorKind, String)>,
+}
+
+impl<R: Read> Read for SnappyReader<R> {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        if let Some((kind, msg)) = &self.error {
+            return Err(io::Error::new(*kind, msg.as_str()));
+        }
+        while self.pos == self.buf.len() {
+            match self.read_chunk() {
+                Ok(true) => {}
+                Ok(false) => return Ok(0),
+                Err(e) => {
+                    if matches!(
+                        e.kind(),
+                        io::ErrorKind::InvalidData | io::ErrorKind::UnexpectedEof
+                    ) {
+                        self.error = Some((e.kind(), e.to_string()));
+                    }
+                    return Err(e);
+                }
+            }
+        }
+        let n = out.len().min(self.buf.len() - self.pos);
+        out[..n].copy_from_slice(&self.buf[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+impl<R: Read> SnappyReader<R> {
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            buf: Vec::with_capacity(MAX_BLOCK_LEN),
+            pos: 0,
+            scratch: Vec::with_capacity(MAX_BLOCK_LEN),
+            header_read: false,
+            error: None,
+        }
+    }
+
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    // reads the next chunk into the buffer.  Returns false at the end
+    // of the stream.
+    fn read_chunk(&mut self) -> io::Result<bool> {
+        let mut header = [0u8; 4];
+        if !read_exact_or_eof(&mut self.inner, &mut header)? {
+            return Ok(false);
+        }
+        let chunk_type = header[0];
+        let len = u32::from_le_bytes([header[1], header[2], header[3], 0]) as usize;
+        if !self.header_read && chunk_type != CHUNK_STREAM_IDENTIFIER {
+            return Err(invalid_data("missing stream identifier"));
+        }
+        let mut chunk = vec![0u8; len];
+        self.inner.read_exact(&mut chunk)?;
+        match chunk_type {
+            CHUNK_STREAM_IDENTIFIER => {
+                if chunk != STREAM_IDENTIFIER {
+                    return Err(invalid_data("invalid stream identifier"));
+                }
+                self.header_read = true;
+            }
+            CHUNK_COMPRESSED | CHUNK_UNCOMPRESSED => {
+                if len < 4 {
+                    return Err(invalid_data("too short data chunk"));
+                }
+                let checksum = u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+                let data = &chunk[4..];
+                self.buf.clear();
+                self.pos = 0;
+                let block = &mut self.scratch;
+                block.clear();
+                if chunk_type == CHUNK_COMPRESSED {
+                    let len = uncompressed_length(data)?;
+                    if len > MAX_BLOCK_LEN {
+                        return Err(invalid_data("too long data chunk"));
+                    }
+                    block.resize(len, 0);
+                    let len = uncompress_into(data, block)?;
+                    block.truncate(len);
+                } else {
+                    if data.len() > MAX_BLOCK_LEN {
+                        return Err(invalid_data("too long data chunk"));
+                    }
+                    block.extend_from_slice(data);
+                }
+                if masked_crc32c(block) != checksum {
+                    return Err(invalid_data("checksum mismatch"));
+                }
+                std::mem::swap(&mut self.buf, &mut self.scratch);
+            }
+            0x02..=0x7f => return Err(invalid_data("reserved unskippable chunk")),
+            // padding and the reserved skippable chunks.
+            CHUNK_PADDING | 0x80..=0xfd => {}
+        }
+        Ok(true)
+    }
+}
+
+fn write_chunk_header<W: Write>(writer: &mut W, chunk_type: u8, len: usize) -> io::Result<()> {
+    let len = (len as u32).to_le_bytes();
+    writer.write_all(&[chunk_type, len[0], len[1], len[2]])
+}
+
+// read_exact, but returns false on the clean end of the stream.
+fn read_exact_or_eof<R: Read>(reader: &mut R, mut buf: &mut [u8]) -> io::Result<bool> {
+    let mut eof = true;
+    while !buf.is_empty() {
+        match reader.read(buf) {
+            Ok(0) if eof => return Ok(false),
+            Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+            Ok(n) => {
+                buf = &mut buf[n..];
+                eof = false;
+            }
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(true)
+}
+
+fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// CRC-32C (Castagnoli) checksum, masked as specified by the framing
+/// format.
+fn masked_crc32c(data: &[u8]) -> u32 {
+    let crc = crc32c(data);
+    (crc.rotate_right(15)).wrapping_add(0xa282ead8)
+}
+
+fn crc32c(data: &[u8]) -> u32 {
+    const POLY: u32 = 0x82f63b78;
+    const TABLE: [u32; 256] = {
+        let mut table = [0u32; 256];
+        let mut i = 0;
+        while i < 256 {
+            let mut crc = i as u32;
+            let mut j = 0;
+            while j < 8 {
+                crc = if crc & 1 != 0 {
+                    (crc >> 1) ^ POLY
+                } else {
+                    crc >> 1
+                };
+                j += 1;
+            }
+            table[i] = crc;
+            i += 1;
+        }
+        table
+    };
+    !data.iter().fold(!0u32, |crc, &b| {
+        TABLE[((crc ^ b as u32) & 0xff) as usize] ^ (crc >> 8)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{crc32c, SnappyReader, SnappyWriter, MAX_BLOCK_LEN};
+    use std::io::{self, Read, Write};
+
+    fn round_trip(data: &[u8]) -> Vec<u8> {
+        let mut writer = SnappyWriter::new(vec![]);
+        writer.write_all(data).unwrap();
+        let framed = writer.into_inner().unwrap();
+        let mut out = vec![];
+        SnappyReader::new(&framed[..])
+            .read_to_end(&mut out)
+            .unwrap();
+        out
+    }
+
+    #[test]
+    fn crc32c_check() {
+        assert_eq!(crc32c(b"123456789"), 0xe3069283);
+    }
+
+    #[test]
+    fn empty() {
+        let framed = SnappyWriter::new(vec![]).into_inner().unwrap();
+        assert_eq!(framed, b"\xff\x06\x00\x00sNaPpY");
+        assert_eq!(round_trip(b""), b"");
+    }
+
+    #[test]
+    fn multiple_chunks() {
+        let data: Vec<u8> = (0..MAX_BLOCK_LEN * 3 + 100)
+            .map(|i| (i % 251) as u8)
+            .collect();
+        assert_eq!(round_trip(&data), data);
+    }
+
+    #[test]
+    fn uncompressible() {
+        let mut seed = 0x2545_f491_u32;
+        let data: Vec<u8> = (0..1000)
+            .map(|_| {
+                seed ^= seed << 13;
+                seed ^= seed >> 17;
+                seed ^= seed << 5;
+                seed as u8
+            })
+            .collect();
+        let mut writer = SnappyWriter::new(vec![]);
+        writer.write_all(&data).unwrap();
+        let framed = writer.into_inner().unwrap();
+        assert_eq!(framed[10], 0x01);
+        assert_eq!(round_trip(&data), data);
+    }
+
+    #[test]
+    fn skippable_chunks() {
+        let mut framed = b"\xff\x06\x00\x00sNaPpY".to_vec();
+        framed.extend_from_slice(b"\xfe\x02\x00\x00\x00\x00");
+        framed.extend_from_slice(b"\x80\x01\x00\x00x");
+        let mut out = vec![];
+        SnappyReader::new(&framed[..])
+            .read_to_end(&mut out)
+            .unwrap();
+        assert!(out.is_empty());
+    }
+
+    #[test]
+    fn corrupted() {
+        let mut writer = SnappyWriter::new(vec![]);
+        writer.write_all(b"hello, hello, hello, hello").unwrap();
+        let framed = writer.into_inner().unwrap();
+
+        let mut bad = framed.clone();
+        *bad.last_mut().unwrap() ^= 0xff;
+        let err = SnappyReader::new(&bad[..]).read_to_end(&mut vec![]);
+        assert_eq!(err.unwrap_err().kind(), io::ErrorKind::InvalidData);
+
+        let truncated = &framed[..framed.len() - 1];
+        let mut reader = SnappyReader::new(truncated);
+        let err = reader.read_to_end(&mut vec![]);
+        assert_eq!(err.unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+        let err = reader.read(&mut [0; 4]);
+        assert_eq!(err.unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+
+        let err = SnappyReader::new(&framed[10..]).read_to_end(&mut vec![]);
+        assert_eq!(err.unwrap_err().kind(), io::ErrorKind::InvalidData);
+
+        let reserved = b"\xff\x06\x00\x00sNaPpY\x02\x00\x00\x00";
+        let err = SnappyReader::new(&reserved[..]).read_to_end(&mut vec![]);
+        assert_eq!(err.unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+
+    // the failed chunk is never read out, even on the retry.
+    #[test]
+    fn corrupted_compressed_chunk() {
+        let mut framed = b"\xff\x06\x00\x00sNaPpY".to_vec();
+        // 10 bytes long, but no elements.
+        framed.extend_from_slice(b"\x00\x05\x00\x00\x00\x00\x00\x00\x0a");
+        let mut reader = SnappyReader::new(&framed[..]);
+        let mut out = [0xaa; 16];
+        let err = reader.read(&mut out).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        // not the end of the stream.
+        let again = reader.read(&mut out).unwrap_err();
+        assert_eq!(again.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(again.to_string(), err.to_string());
+        assert_eq!(out, [0xaa; 16]);
+    }
+}
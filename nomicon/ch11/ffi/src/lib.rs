@@ -2,106 +2,100 @@
 //!
 //! As in [Rustnomicon].
 //!
+//! The Snappy functions are backed by `libsnappy` by default, or by the
+//! pure Rust implementation with the `backend-rust` feature.  They're
+//! also exported to C, as in [`capi`].
+//!
+//! [`zlib`] binds the system libz, and [`codec::Codec`] abstracts both
+//! of them.
+//!
 //! [rustnomicon]: https://doc.rust-lang.org/nomicon/ffi.html
-use libc::{c_int, size_t};
-
-#[link(name = "snappy")]
-extern "C" {
-    fn snappy_compress(
-        input: *const u8,
-        input_length: size_t,
-        compressed: *mut u8,
-        compressed_len: *mut size_t,
-    ) -> c_int;
-
-    fn snappy_uncompress(
-        compressed: *const u8,
-        compressed_length: size_t,
-        uncompressed: *mut u8,
-        uncompressed_len: *mut size_t,
-    ) -> c_int;
-
-    fn snappy_max_compressed_length(source_length: size_t) -> size_t;
-
-    fn snappy_uncompressed_length(
-        compressed: *const u8,
-        compressed_length: size_t,
-        result: *mut size_t,
-    ) -> c_int;
-
-    fn snappy_validate_compressed_buffer(compressed: *const u8, compressed_length: size_t)
-        -> c_int;
-}
-
-pub fn max_compressed_length(source_length: usize) -> usize {
-    unsafe { snappy_max_compressed_length(source_length as size_t) as usize }
-}
-
-pub fn validate_compressed_buffer(src: &[u8]) -> bool {
-    unsafe {
-        snappy_validate_compressed_buffer(src.as_ptr() as *const u8, src.len() as size_t) == 0
-    }
-}
-
-pub fn compress(src: &[u8]) -> Vec<u8> {
-    unsafe {
-        let srclen = src.len() as size_t;
-        let psrc = src.as_ptr();
-        let mut dstlen = snappy_max_compressed_length(srclen);
-        let mut dst = Vec::with_capacity(dstlen);
-        let pdst = dst.as_mut_ptr();
-        snappy_compress(psrc, srclen, pdst, &mut dstlen);
-        dst.set_len(dstlen);
-        dst
-    }
-}
-
-pub fn uncompress(src: &[u8]) -> Option<Vec<u8>> {
-    unsafe {
-        let srclen = src.len() as size_t;
-        let psrc = src.as_ptr();
-
-        let mut dstlen: size_t = 0;
-        snappy_uncompressed_length(psrc, srclen, &mut dstlen);
-
-        let mut dst = Vec::with_capacity(dstlen as usize);
-        let pdst = dst.as_mut_ptr();
-
-        if snappy_uncompress(psrc, srclen, pdst, &mut dstlen) == 0 {
-            dst.set_len(dstlen as usize);
-            Some(dst)
-        } else {
-            None
-        }
-    }
-}
+pub mod capi;
+pub mod codec;
+mod error;
+mod frame;
+pub mod rust;
+#[cfg(not(feature = "backend-rust"))]
+mod sys;
+pub mod zlib;
+
+pub use error::SnappyError;
+pub use frame::{SnappyReader, SnappyWriter};
+#[cfg(feature = "backend-rust")]
+pub use rust::{
+    compress, compress_into, max_compressed_length, uncompress, uncompress_into,
+    uncompressed_length, validate_compressed_buffer,
+};
+#[cfg(not(feature = "backend-rust"))]
+pub use sys::{
+    compress, compress_into, max_compressed_length, uncompress, uncompress_into,
+    uncompressed_length, validate_compressed_buffer,
+};
 
 #[cfg(test)]
 mod tests {
-    use super::{compress, uncompress, validate_compressed_buffer};
+    use super::{
+        compress, compress_into, max_compressed_length, uncompress, uncompress_into,
+        uncompressed_length, validate_compressed_buffer, SnappyError,
+    };
 
     #[test]
     fn valid() {
         let d = vec![0xde, 0xad, 0xd0, 0x0d];
-        let c: &[u8] = &compress(&d);
+        let c: &[u8] = &compress(&d).unwrap();
         assert!(validate_compressed_buffer(c));
-        assert_eq!(uncompress(c), Some(d));
+        assert_eq!(uncompressed_length(c), Ok(4));
+        assert_eq!(uncompress(c), Ok(d));
     }
 
     #[test]
     fn invalid() {
         let c = vec![0, 0, 0, 0];
         assert!(!validate_compressed_buffer(&c));
-        assert_eq!(uncompress(&c), None);
+        assert_eq!(uncompress(&c), Err(SnappyError::InvalidInput));
     }
 
     #[test]
     fn empty() {
         let d = vec![];
         assert!(!validate_compressed_buffer(&d));
-        assert_eq!(uncompress(&d), None);
-        let c = compress(&d);
+        assert_eq!(uncompressed_length(&d), Err(SnappyError::InvalidInput));
+        assert_eq!(uncompress(&d), Err(SnappyError::InvalidInput));
+        let c = compress(&d).unwrap();
         assert!(validate_compressed_buffer(&c));
-        assert_eq!(uncompress(&c), Some(d));
+        assert_eq!(uncompress(&c), Ok(d));
+    }
+
+    #[test]
+    fn into_buffer() {
+        let d = b"into buffer, into buffer, into buffer";
+        let mut c = [0u8; 128];
+        assert!(c.len() >= max_compressed_length(d.len()));
+        let clen = compress_into(d, &mut c).unwrap();
+        let mut u = [0u8; 128];
+        let ulen = uncompress_into(&c[..clen], &mut u).unwrap();
+        assert_eq!(&u[..ulen], d);
+    }
+
+    // the 4 GiB length is rejected before the allocation, by either
+    // backend.
+    #[test]
+    fn bogus_length() {
+        let c = [0xff, 0xff, 0xff, 0xff, 0x0f, 0x00];
+        assert_eq!(uncompress(&c), Err(SnappyError::InvalidInput));
+    }
+
+    #[test]
+    fn buffer_too_small() {
+        let d = b"buffer too small";
+        assert_eq!(
+            compress_into(d, &mut [0u8; 4]),
+            Err(SnappyError::BufferTooSmall),
+        );
+        let c = compress(d).unwrap();
+        assert_eq!(
+            uncompress_into(&c, &mut [0u8; 4]),
+            Err(SnappyError::BufferTooSmall),
+        );
     }
 }
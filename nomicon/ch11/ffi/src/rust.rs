@@ -0,0 +1,406 @@
+//! Snappy backend in safe Rust, with the same functions as the
+//! `libsnappy` backend.
+//!
+//! It follows the [format description] and produces the same kind of
+//! output as `libsnappy`, e.g. the input is compressed in 64KiB blocks
+//! with the 4 bytes hash table based matching.
+//!
+//! [format description]: https://github.com/google/snappy/blob/main/format_description.txt
+use crate::SnappyError;
+
+// Input is compressed block by block, so that the copy offset fits in
+// the 2 bytes.
+const BLOCK_LEN: usize = 1 << 16;
+
+// Blocks shorter than this are emitted as a literal.
+const INPUT_MARGIN: usize = 15;
+
+const HASH_BITS: u32 = 14;
+
+const TAG_LITERAL: u8 = 0b00;
+const TAG_COPY1: u8 = 0b01;
+const TAG_COPY2: u8 = 0b10;
+const TAG_COPY4: u8 = 0b11;
+
+pub fn max_compressed_length(source_length: usize) -> usize {
+    32 + source_length + source_length / 6
+}
+
+pub fn validate_compressed_buffer(src: &[u8]) -> bool {
+    let (len, mut elements) = match Elements::new(src) {
+        Ok(elements) => elements,
+        Err(_) => return false,
+    };
+    let mut produced = 0usize;
+    for element in &mut elements {
+        let n = match element {
+            Ok(Element::Literal(literal)) => literal.len(),
+            Ok(Element::Copy { offset, len }) if offset <= produced => len,
+            _ => return false,
+        };
+        produced += n;
+        if produced > len {
+            return false;
+        }
+    }
+    produced == len
+}
+
+/// Compresses `src` into the newly allocated buffer.
+pub fn compress(src: &[u8]) -> Result<Vec<u8>, SnappyError> {
+    let mut dst = vec![0; max_compressed_length(src.len())];
+    let len = compress_into(src, &mut dst)?;
+    dst.truncate(len);
+    Ok(dst)
+}
+
+/// Compresses `src` into `dst` and returns the compressed length.
+///
+/// `dst` should be at least [`max_compressed_length`] long, otherwise
+/// it fails with [`SnappyError::BufferTooSmall`], as `libsnappy` does.
+pub fn compress_into(src: &[u8], dst: &mut [u8]) -> Result<usize, SnappyError> {
+    if dst.len() < max_compressed_length(src.len()) {
+        return Err(SnappyError::BufferTooSmall);
+    }
+    let len = u32::try_from(src.len()).map_err(|_| SnappyError::InvalidInput)?;
+    let mut out = Output { buf: dst, pos: 0 };
+    out.varint(len);
+    let mut table = vec![0u16; 1 << HASH_BITS];
+    for block in src.chunks(BLOCK_LEN) {
+        table.iter_mut().for_each(|entry| *entry = 0);
+        compress_block(block, &mut table, &mut out);
+    }
+    Ok(out.pos)
+}
+
+/// Returns the uncompressed length of the compressed `src`.
+pub fn uncompressed_length(src: &[u8]) -> Result<usize, SnappyError> {
+    Elements::new(src).map(|(len, _)| len)
+}
+
+/// Uncompresses `src` into the newly allocated buffer.
+pub fn uncompress(src: &[u8]) -> Result<Vec<u8>, SnappyError> {
+    let len = uncompressed_length(src)?;
+    // copy element can't expand more than 64 bytes out of 3 bytes,
+    // which rejects the bogus length before the allocation.
+    if len / 22 > src.len() {
+        return Err(SnappyError::InvalidInput);
+    }
+    let mut dst = vec![0; len];
+    uncompress_into(src, &mut dst)?;
+    Ok(dst)
+}
+
+/// Uncompresses `src` into `dst` and returns the uncompressed length.
+///
+/// `dst` should be at least [`uncompressed_length`] long, otherwise
+/// it fails with [`SnappyError::BufferTooSmall`].
+pub fn uncompress_into(src: &[u8], dst: &mut [u8]) -> Result<usize, SnappyError> {
+    let (len, elements) = Elements::new(src)?;
+    if dst.len() < len {
+        return Err(SnappyError::BufferTooSmall);
+    }
+    let dst = &mut dst[..len];
+    let mut pos = 0;
+    for element in elements {
+        match element? {
+            Element::Literal(literal) => {
+                dst.get_mut(pos..pos + literal.len())
+                    .ok_or(SnappyError::InvalidInput)?
+                    .copy_from_slice(literal);
+                pos += literal.len();
+            }
+            Element::Copy { offset, len } => {
+                if offset > pos || len > dst.len() - pos {
+                    return Err(SnappyError::InvalidInput);
+                }
+                // byte by byte, as the source may overlap the destination.
+                for i in pos..pos + len {
+                    dst[i] = dst[i - offset];
+                }
+                pos += len;
+            }
+        }
+    }
+    if pos != len {
+        return Err(SnappyError::InvalidInput);
+    }
+    Ok(len)
+}
+
+fn compress_block(block: &[u8], table: &mut [u16], out: &mut Output<'_>) {
+    let mut next_emit = 0;
+    if block.len() >= INPUT_MARGIN {
+        let limit = block.len() - INPUT_MARGIN;
+        let mut ip = 1;
+        'block: loop {
+            // look for the 4 bytes match, skipping faster and faster
+            // in case there is no match.
+            let mut skip = 32;
+            let mut next_ip = ip;
+            let mut candidate;
+            loop {
+                ip = next_ip;
+                next_ip = ip + (skip >> 5);
+                skip += 1;
+                if next_ip > limit {
+                    break 'block;
+                }
+                let h = hash(load32(block, ip));
+                candidate = table[h] as usize;
+                table[h] = ip as u16;
+                if load32(block, ip) == load32(block, candidate) {
+                    break;
+                }
+            }
+            out.literal(&block[next_emit..ip]);
+
+            // emit the copies as long as the next position matches.
+            loop {
+                let base = ip;
+                let matched = 4 + match_len(block, candidate + 4, ip + 4);
+                ip += matched;
+                out.copy(base - candidate, matched);
+                next_emit = ip;
+                if ip >= limit {
+                    break 'block;
+                }
+                table[hash(load32(block, ip - 1))] = (ip - 1) as u16;
+                let h = hash(load32(block, ip));
+                candidate = table[h] as usize;
+                table[h] = ip as u16;
+                if load32(block, ip) != load32(block, candidate) {
+                    break;
+                }
+            }
+            ip += 1;
+        }
+    }
+    if next_emit < block.len() {
+        out.literal(&block[next_emit..]);
+    }
+}
+
+fn load32(block: &[u8], pos: usize) -> u32 {
+    u32::from_le_bytes([block[pos], block[pos + 1], block[pos + 2], block[pos + 3]])
+}
+
+fn hash(data: u32) -> usize {
+    (data.wrapping_mul(0x1e35a7bd) >> (32 - HASH_BITS)) as usize
+}
+
+// length of the common prefix of block[a..] and block[b..], a < b.
+fn match_len(block: &[u8], a: usize, b: usize) -> usize {
+    block[b..]
+        .iter()
+        .zip(&block[a..])
+        .take_while(|(x, y)| x == y)
+        .count()
+}
+
+// Compressed output, which is guaranteed to fit by the
+// max_compressed_length check.
+struct Output<'a> {
+    buf: &'a mut [u8],
+    pos: usize,
+}
+
+impl Output<'_> {
+    fn byte(&mut self, b: u8) {
+        self.buf[self.pos] = b;
+        self.pos += 1;
+    }
+
+    fn bytes(&mut self, bytes: &[u8]) {
+        self.buf[self.pos..self.pos + bytes.len()].copy_from_slice(bytes);
+        self.pos += bytes.len();
+    }
+
+    fn varint(&mut self, mut n: u32) {
+        while n >= 0x80 {
+            self.byte(n as u8 | 0x80);
+            n >>= 7;
+        }
+        self.byte(n as u8);
+    }
+
+    fn literal(&mut self, literal: &[u8]) {
+        let n = literal.len() - 1;
+        if n < 60 {
+            self.byte((n as u8) << 2 | TAG_LITERAL);
+        } else {
+            // 1 to 4 bytes length after the tag.
+            let bytes = (n as u32).to_le_bytes();
+            let count = 4 - (n as u32).leading_zeros() as usize / 8;
+            self.byte((59 + count as u8) << 2 | TAG_LITERAL);
+            self.bytes(&bytes[..count]);
+        }
+        self.bytes(literal);
+    }
+
+    fn copy(&mut self, offset: usize, mut len: usize) {
+        // split the long copy into the 64 bytes chunks, keeping the
+        // last one at least 4 bytes long.
+        while len >= 68 {
+            self.copy2(offset, 64);
+            len -= 64;
+        }
+        if len > 64 {
+            self.copy2(offset, 60);
+            len -= 60;
+        }
+        if len < 12 && offset < 2048 {
+            self.byte(((offset >> 8) as u8) << 5 | ((len - 4) as u8) << 2 | TAG_COPY1);
+            self.byte(offset as u8);
+        } else {
+            self.copy2(offset, len);
+        }
+    }
+
+    fn copy2(&mut self, offset: usize, len: usize) {
+        self.byte(((len - 1) as u8) << 2 | TAG_COPY2);
+        self.bytes(&(offset as u16).to_le_bytes());
+    }
+}
+
+enum Element<'a> {
+    Literal(&'a [u8]),
+    Copy { offset: usize, len: usize },
+}
+
+// Iterator of the compressed elements, after the uncompressed length.
+struct Elements<'a>(&'a [u8]);
+
+impl<'a> Elements<'a> {
+    // returns the uncompressed length and the elements.
+    fn new(src: &'a [u8]) -> Result<(usize, Self), SnappyError> {
+        let mut len = 0u32;
+        for (i, &b) in src.iter().enumerate().take(5) {
+            let bits = (b & 0x7f) as u32;
+            // the fifth byte can carry only 4 bits.
+            if i == 4 && (bits > 0x0f || b & 0x80 != 0) {
+                break;
+            }
+            len |= bits << (7 * i);
+            if b & 0x80 == 0 {
+                return Ok((len as usize, Self(&src[i + 1..])));
+            }
+        }
+        Err(SnappyError::InvalidInput)
+    }
+
+    fn take(&mut self, n: usize) -> Result<&'a [u8], SnappyError> {
+        if self.0.len() < n {
+            return Err(SnappyError::InvalidInput);
+        }
+        let (head, tail) = self.0.split_at(n);
+        self.0 = tail;
+        Ok(head)
+    }
+
+    fn element(&mut self, tag: u8) -> Result<Element<'a>, SnappyError> {
+        let element = match tag & 0b11 {
+            TAG_LITERAL => {
+                let len = match (tag >> 2) as usize {
+                    n @ 0..=59 => n + 1,
+                    n => {
+                        let bytes = self.take(n - 59)?;
+                        let mut len = [0u8; 4];
+                        len[..bytes.len()].copy_from_slice(bytes);
+                        u32::from_le_bytes(len) as usize + 1
+                    }
+                };
+                Element::Literal(self.take(len)?)
+            }
+            TAG_COPY1 => {
+                let low = self.take(1)?[0] as usize;
+                Element::Copy {
+                    offset: (tag as usize >> 5) << 8 | low,
+                    len: 4 + (tag as usize >> 2 & 0b111),
+                }
+            }
+            TAG_COPY2 => {
+                let offset = self.take(2)?;
+                Element::Copy {
+                    offset: u16::from_le_bytes([offset[0], offset[1]]) as usize,
+                    len: (tag as usize >> 2) + 1,
+                }
+            }
+            _ => {
+                debug_assert_eq!(tag & 0b11, TAG_COPY4);
+                let offset = self.take(4)?;
+                Element::Copy {
+                    offset: u32::from_le_bytes([offset[0], offset[1], offset[2], offset[3]])
+                        as usize,
+                    len: (tag as usize >> 2) + 1,
+                }
+            }
+        };
+        match element {
+            Element::Copy { offset: 0, .. } => Err(SnappyError::InvalidInput),
+            element => Ok(element),
+        }
+    }
+}
+
+impl<'a> Iterator for Elements<'a> {
+    type Item = Result<Element<'a>, SnappyError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (&tag, rest) = self.0.split_first()?;
+        self.0 = rest;
+        Some(self.element(tag))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        compress, max_compressed_length, uncompress, uncompressed_length,
+        validate_compressed_buffer,
+    };
+    use crate::SnappyError;
+
+    #[test]
+    fn literal_only() {
+        let c = compress(b"abc").unwrap();
+        assert_eq!(c, [3, 2 << 2, b'a', b'b', b'c']);
+        assert_eq!(uncompress(&c).unwrap(), b"abc");
+    }
+
+    #[test]
+    fn copy() {
+        let d = b"abcdabcdabcdabcdabcdabcdabcdabcd";
+        let c = compress(d).unwrap();
+        assert!(c.len() < d.len());
+        assert!(validate_compressed_buffer(&c));
+        assert_eq!(uncompress(&c).unwrap(), d);
+    }
+
+    #[test]
+    fn long_literal_and_copy() {
+        let d: Vec<u8> = (0..200_000u32).map(|i| (i * 7 / 3) as u8).collect();
+        let c = compress(&d).unwrap();
+        assert!(c.len() <= max_compressed_length(d.len()));
+        assert_eq!(uncompressed_length(&c), Ok(d.len()));
+        assert_eq!(uncompress(&c).unwrap(), d);
+    }
+
+    #[test]
+    fn copy_before_start() {
+        // 4 bytes, then the copy with offset 5.
+        let c = [8, 3 << 2, b'a', b'b', b'c', b'd', 0b01, 5];
+        assert!(!validate_compressed_buffer(&c));
+        assert_eq!(uncompress(&c), Err(SnappyError::InvalidInput));
+    }
+
+    #[test]
+    fn bogus_length() {
+        // 4GiB - 1 out of the two bytes.
+        let c = [0xff, 0xff, 0xff, 0xff, 0x0f, 0x00];
+        assert_eq!(uncompressed_length(&c), Ok(u32::MAX as usize));
+        assert_eq!(uncompress(&c), Err(SnappyError::InvalidInput));
+        let c = [0xff, 0xff, 0xff, 0xff, 0x1f];
+        assert_eq!(uncompressed_length(&c), Err(SnappyError::InvalidInput));
+    }
+}
@@ -0,0 +1,132 @@
+//! Snappy backend calling `libsnappy` through its C API.
+use crate::SnappyError;
+use libc::{c_int, size_t};
+
+// snappy_status in snappy-c.h.
+const SNAPPY_OK: c_int = 0;
+const SNAPPY_INVALID_INPUT: c_int = 1;
+const SNAPPY_BUFFER_TOO_SMALL: c_int = 2;
+
+// linked by build.rs to the system libsnappy.
+extern "C" {
+    fn snappy_compress(
+        input: *const u8,
+        input_length: size_t,
+        compressed: *mut u8,
+        compressed_len: *mut size_t,
+    ) -> c_int;
+
+    fn snappy_uncompress(
+        compressed: *const u8,
+        compressed_length: size_t,
+        uncompressed: *mut u8,
+        uncompressed_len: *mut size_t,
+    ) -> c_int;
+
+    fn snappy_max_compressed_length(source_length: size_t) -> size_t;
+
+    fn snappy_uncompressed_length(
+        compressed: *const u8,
+        compressed_length: size_t,
+        result: *mut size_t,
+    ) -> c_int;
+
+    fn snappy_validate_compressed_buffer(compressed: *const u8, compressed_length: size_t)
+        -> c_int;
+}
+
+pub fn max_compressed_length(source_length: usize) -> usize {
+    unsafe { snappy_max_compressed_length(source_length as size_t) as usize }
+}
+
+pub fn validate_compressed_buffer(src: &[u8]) -> bool {
+    unsafe { snappy_validate_compressed_buffer(src.as_ptr(), src.len() as size_t) == 0 }
+}
+
+/// Compresses `src` into the newly allocated buffer.
+pub fn compress(src: &[u8]) -> Result<Vec<u8>, SnappyError> {
+    let mut dst = Vec::with_capacity(max_compressed_length(src.len()));
+    unsafe {
+        let len = raw_compress(src, dst.as_mut_ptr(), dst.capacity())?;
+        dst.set_len(len);
+    }
+    Ok(dst)
+}
+
+/// Compresses `src` into `dst` and returns the compressed length.
+///
+/// `dst` should be at least [`max_compressed_length`] long, otherwise
+/// it may fail with [`SnappyError::BufferTooSmall`].
+pub fn compress_into(src: &[u8], dst: &mut [u8]) -> Result<usize, SnappyError> {
+    unsafe { raw_compress(src, dst.as_mut_ptr(), dst.len()) }
+}
+
+/// Returns the uncompressed length of the compressed `src`.
+pub fn uncompressed_length(src: &[u8]) -> Result<usize, SnappyError> {
+    let mut len: size_t = 0;
+    unsafe {
+        check(snappy_uncompressed_length(
+            src.as_ptr(),
+            src.len() as size_t,
+            &mut len,
+        ))?;
+    }
+    Ok(len as usize)
+}
+
+/// Uncompresses `src` into the newly allocated buffer.
+pub fn uncompress(src: &[u8]) -> Result<Vec<u8>, SnappyError> {
+    let len = uncompressed_length(src)?;
+    // copy element can't expand more than 64 bytes out of 3 bytes,
+    // which rejects the bogus length before the allocation.
+    if len / 22 > src.len() {
+        return Err(SnappyError::InvalidInput);
+    }
+    let mut dst = Vec::with_capacity(len);
+    unsafe {
+        let len = raw_uncompress(src, dst.as_mut_ptr(), dst.capacity())?;
+        dst.set_len(len);
+    }
+    Ok(dst)
+}
+
+/// Uncompresses `src` into `dst` and returns the uncompressed length.
+///
+/// `dst` should be at least [`uncompressed_length`] long, otherwise
+/// it fails with [`SnappyError::BufferTooSmall`].
+pub fn uncompress_into(src: &[u8], dst: &mut [u8]) -> Result<usize, SnappyError> {
+    unsafe { raw_uncompress(src, dst.as_mut_ptr(), dst.len()) }
+}
+
+// `dst` should be valid for `dstlen` bytes write.
+unsafe fn raw_compress(src: &[u8], dst: *mut u8, dstlen: usize) -> Result<usize, SnappyError> {
+    let mut dstlen = dstlen as size_t;
+    check(snappy_compress(
+        src.as_ptr(),
+        src.len() as size_t,
+        dst,
+        &mut dstlen,
+    ))?;
+    Ok(dstlen as usize)
+}
+
+// `dst` should be valid for `dstlen` bytes write.
+unsafe fn raw_uncompress(src: &[u8], dst: *mut u8, dstlen: usize) -> Result<usize, SnappyError> {
+    let mut dstlen = dstlen as size_t;
+    check(snappy_uncompress(
+        src.as_ptr(),
+        src.len() as size_t,
+        dst,
+        &mut dstlen,
+    ))?;
+    Ok(dstlen as usize)
+}
+
+fn check(status: c_int) -> Result<(), SnappyError> {
+    match status {
+        SNAPPY_OK => Ok(()),
+        SNAPPY_INVALID_INPUT => Err(SnappyError::InvalidInput),
+        SNAPPY_BUFFER_TOO_SMALL => Err(SnappyError::BufferTooSmall),
+        status => Err(SnappyError::Unknown(status)),
+    }
+}
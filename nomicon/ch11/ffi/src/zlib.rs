@@ -0,0 +1,624 @@
+//! zlib bindings, streaming the deflate and inflate through `z_stream`.
+//!
+//! The [`Deflate`] and [`Inflate`] streams are the thin wrappers of the
+//! `z_stream` state, and [`ZlibWriter`] and [`ZlibReader`] are the
+//! `Write` and `Read` adaptors on top of them, as [`SnappyWriter`] and
+//! [`SnappyReader`] are for the Snappy framing format.
+//!
+//! [`SnappyWriter`]: crate::SnappyWriter
+//! [`SnappyReader`]: crate::SnappyReader
+use libc::c_int;
+use std::error::Error;
+use std::fmt::{self, Display};
+use std::io::{self, Read, Write};
+use std::mem;
+
+/// Default compression level, e.g. `Z_DEFAULT_COMPRESSION`.
+pub const DEFAULT_LEVEL: i32 = sys::Z_DEFAULT_COMPRESSION;
+
+// Output and input buffer length of the adaptors.
+const BUF_LEN: usize = 32 * 1024;
+
+mod sys {
+    //! Raw `zlib.h` bindings.
+    use libc::{c_char, c_int, c_uint, c_ulong, c_void};
+
+    pub const Z_NO_FLUSH: c_int = 0;
+    pub const Z_SYNC_FLUSH: c_int = 2;
+    pub const Z_FINISH: c_int = 4;
+
+    pub const Z_OK: c_int = 0;
+    pub const Z_STREAM_END: c_int = 1;
+    pub const Z_NEED_DICT: c_int = 2;
+    pub const Z_ERRNO: c_int = -1;
+    pub const Z_STREAM_ERROR: c_int = -2;
+    pub const Z_DATA_ERROR: c_int = -3;
+    pub const Z_MEM_ERROR: c_int = -4;
+    pub const Z_BUF_ERROR: c_int = -5;
+    pub const Z_VERSION_ERROR: c_int = -6;
+
+    pub const Z_DEFAULT_COMPRESSION: c_int = -1;
+
+    type AllocFunc = Option<unsafe extern "C" fn(*mut c_void, c_uint, c_uint) -> *mut c_void>;
+    type FreeFunc = Option<unsafe extern "C" fn(*mut c_void, *mut c_void)>;
+
+    #[repr(C)]
+    pub struct z_stream {
+        pub next_in: *const u8,
+        pub avail_in: c_uint,
+        pub total_in: c_ulong,
+        pub next_out: *mut u8,
+        pub avail_out: c_uint,
+        pub total_out: c_ulong,
+        pub msg: *const c_char,
+        pub state: *mut c_void,
+        pub zalloc: AllocFunc,
+        pub zfree: FreeFunc,
+        pub opaque: *mut c_void,
+        pub data_type: c_int,
+        pub adler: c_ulong,
+        pub reserved: c_ulong,
+    }
+
+    #[link(name = "z")]
+    extern "C" {
+        pub fn zlibVersion() -> *const c_char;
+        pub fn deflateInit_(
+            strm: *mut z_stream,
+            level: c_int,
+            version: *const c_char,
+            stream_size: c_int,
+        ) -> c_int;
+        pub fn deflate(strm: *mut z_stream, flush: c_int) -> c_int;
+        pub fn deflateEnd(strm: *mut z_stream) -> c_int;
+        pub fn inflateInit_(
+            strm: *mut z_stream,
+            version: *const c_char,
+            stream_size: c_int,
+        ) -> c_int;
+        pub fn inflate(strm: *mut z_stream, flush: c_int) -> c_int;
+        pub fn inflateEnd(strm: *mut z_stream) -> c_int;
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ZlibError {
+    /// `Z_NEED_DICT`, e.g. the preset dictionary is required.
+    NeedDict,
+    /// `Z_ERRNO`.
+    Errno,
+    /// `Z_STREAM_ERROR`, e.g. the inconsistent stream state.
+    Stream,
+    /// `Z_DATA_ERROR`, e.g. the corrupted input.
+    Data,
+    /// `Z_MEM_ERROR`.
+    Mem,
+    /// `Z_BUF_ERROR`, e.g. no progress was possible.
+    Buf,
+    /// `Z_VERSION_ERROR`, e.g. the incompatible libz.
+    Version,
+    /// Status not defined in `zlib.h`.
+    Unknown(i32),
+    /// The uncompressed data is longer than the limit of [`uncompress`].
+    TooLong,
+}
+
+impl Error for ZlibError {}
+
+impl Display for ZlibError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NeedDict => write!(f, "zlib dictionary needed"),
+            Self::Errno => write!(f, "zlib file error"),
+            Self::Stream => write!(f, "zlib stream error"),
+            Self::Data => write!(f, "zlib data error"),
+            Self::Mem => write!(f, "zlib insufficient memory"),
+            Self::Buf => write!(f, "zlib buffer error"),
+            Self::Version => write!(f, "incompatible zlib version"),
+            Self::Unknown(status) => write!(f, "unknown zlib status {status}"),
+            Self::TooLong => write!(f, "zlib uncompressed data too long"),
+        }
+    }
+}
+
+impl From<ZlibError> for io::Error {
+    fn from(e: ZlibError) -> Self {
+        io::Error::new(io::ErrorKind::InvalidData, e)
+    }
+}
+
+/// Flush mode of [`Deflate::deflate`] and [`Inflate::inflate`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Flush {
+    None,
+    Sync,
+    Finish,
+}
+
+/// Status of the successful [`Deflate::deflate`] and
+/// [`Inflate::inflate`] call.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Ok,
+    StreamEnd,
+    /// `Z_BUF_ERROR`, which is not fatal but no progress was possible.
+    BufError,
+}
+
+/// Result of the single [`Deflate::deflate`] or [`Inflate::inflate`]
+/// call, e.g. the bytes consumed out of the input and the bytes
+/// produced into the output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Progress {
+    pub consumed: usize,
+    pub produced: usize,
+    pub status: Status,
+}
+
+/// Deflate stream.
+pub struct Deflate(Stream);
+
+impl Drop for Deflate {
+    fn drop(&mut self) {
+        unsafe {
+            sys::deflateEnd(self.0.as_mut_ptr());
+        }
+    }
+}
+
+impl Deflate {
+    /// Fails with [`ZlibError::Stream`] if `level` is not between -1 and
+    /// 9.
+    pub fn new(level: i32) -> Result<Self, ZlibError> {
+        if !(-1..=9).contains(&level) {
+            return Err(ZlibError::Stream);
+        }
+        let mut stream = Stream::new();
+        unsafe {
+            let status = sys::deflateInit_(
+                stream.as_mut_ptr(),
+                level,
+                sys::zlibVersion(),
+                mem::size_of::<sys::z_stream>() as c_int,
+            );
+            check(status)?;
+        }
+        Ok(Self(stream))
+    }
+
+    pub fn total_in(&self) -> u64 {
+        self.0.total_in()
+    }
+
+    pub fn total_out(&self) -> u64 {
+        self.0.total_out()
+    }
+
+    pub fn deflate(
+        &mut self,
+        input: &[u8],
+        output: &mut [u8],
+        flush: Flush,
+    ) -> Result<Progress, ZlibError> {
+        self.0.run(input, output, flush, |strm, flush| unsafe {
+            sys::deflate(strm, flush)
+        })
+    }
+}
+
+/// Inflate stream.
+pub struct Inflate(Stream);
+
+impl Drop for Inflate {
+    fn drop(&mut self) {
+        unsafe {
+            sys::inflateEnd(self.0.as_mut_ptr());
+        }
+    }
+}
+
+impl Inflate {
+    pub fn new() -> Result<Self, ZlibError> {
+        let mut stream = Stream::new();
+        unsafe {
+            let status = sys::inflateInit_(
+                stream.as_mut_ptr(),
+                sys::zlibVersion(),
+                mem::size_of::<sys::z_stream>() as c_int,
+            );
+            check(status)?;
+        }
+        Ok(Self(stream))
+    }
+
+    pub fn total_in(&self) -> u64 {
+        self.0.total_in()
+    }
+
+    pub fn total_out(&self) -> u64 {
+        self.0.total_out()
+    }
+
+    pub fn inflate(
+        &mut self,
+        input: &[u8],
+        output: &mut [u8],
+        flush: Flush,
+    ) -> Result<Progress, ZlibError> {
+        self.0.run(input, output, flush, |strm, flush| unsafe {
+            sys::inflate(strm, flush)
+        })
+    }
+}
+
+// Boxed, as zlib keeps the pointer to the z_stream in its state.
+struct Stream(Box<sys::z_stream>);
+
+impl Stream {
+    fn new() -> Self {
+        // null zalloc, zfree and opaque select the default allocator.
+        Self(Box::new(unsafe { mem::zeroed() }))
+    }
+
+    fn as_mut_ptr(&mut self) -> *mut sys::z_stream {
+        &mut *self.0
+    }
+
+    // c_ulong is 32 bits on Windows.
+    #[allow(clippy::unnecessary_cast)]
+    fn total_in(&self) -> u64 {
+        self.0.total_in as u64
+    }
+
+    #[allow(clippy::unnecessary_cast)]
+    fn total_out(&self) -> u64 {
+        self.0.total_out as u64
+    }
+
+    fn run<F>(
+        &mut self,
+        input: &[u8],
+        output: &mut [u8],
+        flush: Flush,
+        f: F,
+    ) -> Result<Progress, ZlibError>
+    where
+        F: FnOnce(*mut sys::z_stream, c_int) -> c_int,
+    {
+        // avail_in and avail_out are 32 bits.
+        let input = &input[..input.len().min(u32::MAX as usize)];
+        let output_len = output.len().min(u32::MAX as usize);
+        let flush = match flush {
+            Flush::None => sys::Z_NO_FLUSH,
+            Flush::Sync => sys::Z_SYNC_FLUSH,
+            Flush::Finish => sys::Z_FINISH,
+        };
+        self.0.next_in = input.as_ptr();
+        self.0.avail_in = input.len() as u32;
+        self.0.next_out = output.as_mut_ptr();
+        self.0.avail_out = output_len as u32;
+        let status = f(self.as_mut_ptr(), flush);
+        let consumed = input.len() - self.0.avail_in as usize;
+        let produced = output_len - self.0.avail_out as usize;
+        // don't leave the dangling pointers behind.
+        self.0.next_in = std::ptr::null();
+        self.0.avail_in = 0;
+        self.0.next_out = std::ptr::null_mut();
+        self.0.avail_out = 0;
+        let status = match status {
+            sys::Z_BUF_ERROR => Status::BufError,
+            status => match check(status)? {
+                sys::Z_STREAM_END => Status::StreamEnd,
+                _ => Status::Ok,
+            },
+        };
+        Ok(Progress {
+            consumed,
+            produced,
+            status,
+        })
+    }
+}
+
+// returns the non-negative status as is.
+fn check(status: c_int) -> Result<c_int, ZlibError> {
+    match status {
+        sys::Z_OK | sys::Z_STREAM_END => Ok(status),
+        sys::Z_NEED_DICT => Err(ZlibError::NeedDict),
+        sys::Z_ERRNO => Err(ZlibError::Errno),
+        sys::Z_STREAM_ERROR => Err(ZlibError::Stream),
+        sys::Z_DATA_ERROR => Err(ZlibError::Data),
+        sys::Z_MEM_ERROR => Err(ZlibError::Mem),
+        sys::Z_BUF_ERROR => Err(ZlibError::Buf),
+        sys::Z_VERSION_ERROR => Err(ZlibError::Version),
+        status => Err(ZlibError::Unknown(status)),
+    }
+}
+
+/// Compresses `src` in the zlib format.
+pub fn compress(src: &[u8], level: i32) -> Result<Vec<u8>, ZlibError> {
+    let mut deflate = Deflate::new(level)?;
+    let mut dst = vec![0; src.len() / 2 + 64];
+    let mut input = src;
+    loop {
+        let pos = deflate.total_out() as usize;
+        let progress = deflate.deflate(input, &mut dst[pos..], Flush::Finish)?;
+        input = &input[progress.consumed..];
+        if progress.status == Status::StreamEnd {
+            dst.truncate(deflate.total_out() as usize);
+            return Ok(dst);
+        }
+        dst.resize(dst.len() * 2, 0);
+    }
+}
+
+/// Default limit of the uncompressed length, e.g. for [`Codec`].
+///
+/// [`Codec`]: crate::codec::Codec
+pub const MAX_UNCOMPRESSED_LEN: usize = 1 << 30;
+
+/// Uncompresses the zlib format `src`, failing with
+/// [`ZlibError::TooLong`] if it is longer than `max_len` bytes.
+pub fn uncompress(src: &[u8], max_len: usize) -> Result<Vec<u8>, ZlibError> {
+    let mut inflate = Inflate::new()?;
+    // one more byte than the limit, to tell the end of the stream.
+    let cap = max_len.saturating_add(1);
+    let mut dst = vec![0; (src.len() * 4 + 64).min(cap)];
+    let mut input = src;
+    loop {
+        let pos = inflate.total_out() as usize;
+        let progress = inflate.inflate(input, &mut dst[pos..], Flush::Finish)?;
+        input = &input[progress.consumed..];
+        match progress.status {
+            Status::StreamEnd if inflate.total_out() as usize <= max_len => {
+                dst.truncate(inflate.total_out() as usize);
+                return Ok(dst);
+            }
+            // truncated input.
+            Status::BufError if input.is_empty() && pos + progress.produced < dst.len() => {
+                return Err(ZlibError::Buf);
+            }
+            _ if dst.len() == cap => return Err(ZlibError::TooLong),
+            _ => dst.resize((dst.len() * 2).min(cap), 0),
+        }
+    }
+}
+
+/// Compresses the data written to it in the zlib format.
+///
+/// The stream is finished by [`ZlibWriter::finish`] or on drop.
+pub struct ZlibWriter<W: Write> {
+    inner: Option<W>,
+    deflate: Deflate,
+    buf: Vec<u8>,
+}
+
+impl<W: Write> Drop for ZlibWriter<W> {
+    fn drop(&mut self) {
+        if self.inner.is_some() {
+            // ignore the error, as in io::BufWriter.
+            let _ = self.run(&[], Flush::Finish);
+        }
+    }
+}
+
+impl<W: Write> Write for ZlibWriter<W> {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        self.run(data, Flush::None)?;
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.run(&[], Flush::Sync)?;
+        self.get_mut().flush()
+    }
+}
+
+impl<W: Write> ZlibWriter<W> {
+    /// Fails if `level` is not between -1 and 9.
+    pub fn new(inner: W, level: i32) -> io::Result<Self> {
+        Ok(Self {
+            inner: Some(inner),
+            deflate: Deflate::new(level)?,
+            buf: vec![0; BUF_LEN],
+        })
+    }
+
+    pub fn get_ref(&self) -> &W {
+        self.inner
+            .as_ref()
+            .expect("ZlibWriter without inner writer")
+    }
+
+    pub fn get_mut(&mut self) -> &mut W {
+        self.inner
+            .as_mut()
+            .expect("ZlibWriter without inner writer")
+    }
+
+    /// Finishes the stream and returns the inner writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.run(&[], Flush::Finish)?;
+        let mut inner = self.inner.take().expect("ZlibWriter without inner writer");
+        inner.flush()?;
+        Ok(inner)
+    }
+
+    // deflates all the input, writing the output to the inner writer.
+    fn run(&mut self, mut input: &[u8], flush: Flush) -> io::Result<()> {
+        loop {
+            let progress = self.deflate.deflate(input, &mut self.buf, flush)?;
+            input = &input[progress.consumed..];
+            let inner = self
+                .inner
+                .as_mut()
+                .expect("ZlibWriter without inner writer");
+            inner.write_all(&self.buf[..progress.produced])?;
+            let done = match flush {
+                Flush::Finish => progress.status == Status::StreamEnd,
+                // all the pending output is out when the buffer has
+                // space left.
+                _ => input.is_empty() && progress.produced < self.buf.len(),
+            };
+            if done {
+                return Ok(());
+            }
+        }
+    }
+}
+
+/// Uncompresses the zlib format data read from the inner reader.
+pub struct ZlibReader<R: Read> {
+    inner: R,
+    inflate: Inflate,
+    buf: Vec<u8>,
+    pos: usize,
+    len: usize,
+    done: bool,
+}
+
+impl<R: Read> Read for ZlibReader<R> {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        if self.done || out.is_empty() {
+            return Ok(0);
+        }
+        loop {
+            let mut eof = false;
+            if self.pos == self.len {
+                self.pos = 0;
+                self.len = self.inner.read(&mut self.buf)?;
+                eof = self.len == 0;
+            }
+            let input = &self.buf[self.pos..self.len];
+            let progress = self.inflate.inflate(input, out, Flush::None)?;
+            self.pos += progress.consumed;
+            if progress.status == Status::StreamEnd {
+                self.done = true;
+                return Ok(progress.produced);
+            }
+            if progress.produced > 0 {
+                return Ok(progress.produced);
+            }
+            if eof {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+        }
+    }
+}
+
+impl<R: Read> ZlibReader<R> {
+    pub fn new(inner: R) -> io::Result<Self> {
+        Ok(Self {
+            inner,
+            inflate: Inflate::new()?,
+            buf: vec![0; BUF_LEN],
+            pos: 0,
+            len: 0,
+            done: false,
+        })
+    }
+
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        compress, uncompress, Deflate, Flush, Status, ZlibError, ZlibReader, ZlibWriter,
+        DEFAULT_LEVEL, MAX_UNCOMPRESSED_LEN,
+    };
+    use std::io::{self, Read, Write};
+
+    fn data(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i * 7 / 5 % 251) as u8).collect()
+    }
+
+    #[test]
+    fn one_shot() {
+        for len in [0, 1, 100, 1 << 20] {
+            let d = data(len);
+            let c = compress(&d, DEFAULT_LEVEL).unwrap();
+            assert_eq!(uncompress(&c, MAX_UNCOMPRESSED_LEN).unwrap(), d);
+        }
+        // the well-known zlib header.
+        assert_eq!(compress(b"", DEFAULT_LEVEL).unwrap()[0], 0x78);
+    }
+
+    #[test]
+    fn deflate_stream() {
+        let mut deflate = Deflate::new(9).unwrap();
+        let mut out = [0u8; 64];
+        let progress = deflate
+            .deflate(b"abcabcabc", &mut out, Flush::None)
+            .unwrap();
+        assert_eq!(progress.consumed, 9);
+        let progress = deflate.deflate(&[], &mut out, Flush::Finish).unwrap();
+        assert_eq!(progress.status, Status::StreamEnd);
+        assert_eq!(deflate.total_in(), 9);
+    }
+
+    #[test]
+    fn invalid() {
+        assert_eq!(
+            uncompress(b"not zlib", MAX_UNCOMPRESSED_LEN),
+            Err(ZlibError::Data)
+        );
+        let c = compress(&data(1000), DEFAULT_LEVEL).unwrap();
+        assert_eq!(
+            uncompress(&c[..c.len() - 1], MAX_UNCOMPRESSED_LEN),
+            Err(ZlibError::Buf)
+        );
+        for level in [-2, 10] {
+            assert_eq!(compress(b"", level), Err(ZlibError::Stream));
+            assert!(ZlibWriter::new(vec![], level).is_err());
+        }
+    }
+
+    #[test]
+    fn max_len() {
+        // 1 MiB of zeros is compressed to about 1 KiB.
+        let d = vec![0; 1 << 20];
+        let c = compress(&d, DEFAULT_LEVEL).unwrap();
+        assert_eq!(uncompress(&c, d.len()).unwrap(), d);
+        assert_eq!(uncompress(&c, d.len() - 1), Err(ZlibError::TooLong));
+        assert_eq!(uncompress(&c, 0), Err(ZlibError::TooLong));
+        assert_eq!(
+            uncompress(&compress(b"", DEFAULT_LEVEL).unwrap(), 0),
+            Ok(vec![])
+        );
+    }
+
+    #[test]
+    fn writer_reader() {
+        let d = data(300_000);
+        let mut writer = ZlibWriter::new(vec![], DEFAULT_LEVEL).unwrap();
+        for chunk in d.chunks(1000) {
+            writer.write_all(chunk).unwrap();
+        }
+        writer.flush().unwrap();
+        let c = writer.finish().unwrap();
+        assert_eq!(uncompress(&c, MAX_UNCOMPRESSED_LEN).unwrap(), d);
+
+        let mut out = vec![];
+        ZlibReader::new(&c[..])
+            .unwrap()
+            .read_to_end(&mut out)
+            .unwrap();
+        assert_eq!(out, d);
+
+        let err = ZlibReader::new(&c[..c.len() / 2])
+            .unwrap()
+            .read_to_end(&mut vec![]);
+        assert_eq!(err.unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+    }
+}
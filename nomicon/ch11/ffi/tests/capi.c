@@ -0,0 +1,63 @@
+/* Calls the Rust Snappy functions through the generated ffi.h. */
+#include <stdio.h>
+#include <string.h>
+/* ffi.h must not clash with the zlib declarations linked into the crate. */
+#include <zlib.h>
+
+#include "ffi.h"
+
+#define CHECK(cond)                                                   \
+	do {                                                          \
+		if (!(cond)) {                                        \
+			fprintf(stderr, "%s:%d: %s\n", __FILE__,      \
+				__LINE__, #cond);                     \
+			return 1;                                     \
+		}                                                     \
+	} while (0)
+
+int main(void)
+{
+	const char *text = "hello, hello, hello, hello, hello, hello";
+	size_t len = strlen(text);
+	uint8_t compressed[128], uncompressed[128], small[4];
+	size_t clen = sizeof(compressed), ulen = sizeof(uncompressed);
+	size_t n;
+
+	CHECK(ffi_max_compressed_length(len) <= clen);
+	CHECK(ffi_compress((const uint8_t *)text, len, compressed, &clen) ==
+	      FFI_STATUS_OK);
+	CHECK(clen < len);
+	CHECK(ffi_validate_compressed_buffer(compressed, clen) ==
+	      FFI_STATUS_OK);
+	CHECK(ffi_uncompressed_length(compressed, clen, &n) == FFI_STATUS_OK);
+	CHECK(n == len);
+	CHECK(ffi_uncompress(compressed, clen, uncompressed, &ulen) ==
+	      FFI_STATUS_OK);
+	CHECK(ulen == len && memcmp(uncompressed, text, len) == 0);
+	CHECK(ffi_last_panic_message() == NULL);
+
+	/* errors */
+	n = sizeof(small);
+	CHECK(ffi_uncompress(compressed, clen, small, &n) ==
+	      FFI_STATUS_BUFFER_TOO_SMALL);
+	compressed[0] = 0xff;
+	CHECK(ffi_validate_compressed_buffer(compressed, 1) ==
+	      FFI_STATUS_INVALID_INPUT);
+
+	/* panics are caught at the boundary */
+	clen = sizeof(compressed);
+	CHECK(ffi_compress(NULL, len, compressed, &clen) == FFI_STATUS_PANIC);
+	CHECK(ffi_last_panic_message() != NULL);
+	CHECK(strstr(ffi_last_panic_message(), "NULL input") != NULL);
+	CHECK(ffi_uncompressed_length(compressed, clen, NULL) ==
+	      FFI_STATUS_PANIC);
+	CHECK(strstr(ffi_last_panic_message(), "NULL length") != NULL);
+
+	/* and cleared by the next call */
+	CHECK(ffi_validate_compressed_buffer(NULL, 0) ==
+	      FFI_STATUS_INVALID_INPUT);
+	CHECK(ffi_last_panic_message() == NULL);
+
+	printf("ok\n");
+	return 0;
+}
@@ -0,0 +1,47 @@
+//! Compiles tests/capi.c against the generated header and the cdylib,
+//! and runs it.  It needs the `header` feature, e.g.
+//!
+//! ```text
+//! cargo test -p ffi --features header
+//! ```
+use std::env;
+use std::path::PathBuf;
+use std::process::Command;
+
+#[test]
+fn c_program() {
+    // cdylib is built next to the test, e.g. target/<profile>/deps.
+    let exe = env::current_exe().unwrap();
+    let lib_dir = exe.parent().unwrap();
+    let lib = lib_dir.join(format!(
+        "{}ffi{}",
+        env::consts::DLL_PREFIX,
+        env::consts::DLL_SUFFIX
+    ));
+    assert!(lib.exists(), "{} is not built", lib.display());
+    let out_dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR"));
+    let program = out_dir.join("capi");
+
+    let cc = env::var("CC").unwrap_or_else(|_| "cc".to_string());
+    let status = Command::new(cc)
+        .arg("-Wall")
+        .arg("-Werror")
+        .arg("-I")
+        .arg(env!("FFI_INCLUDE_DIR"))
+        .arg(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/capi.c"))
+        .arg(&lib)
+        .arg(format!("-Wl,-rpath,{}", lib_dir.display()))
+        .arg("-o")
+        .arg(&program)
+        .status()
+        .expect("C compiler");
+    assert!(status.success(), "failed to compile tests/capi.c");
+
+    let output = Command::new(&program).output().unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(output.stdout, b"ok\n");
+}
@@ -0,0 +1,208 @@
+//! Cross checks the `libsnappy` backend against the pure Rust backend.
+//!
+//! Without `libsnappy`, e.g. with the `backend-rust` feature, only the
+//! streams assembled by the format description are checked.
+use ffi::rust;
+
+// xorshift, to keep the inputs reproducible.
+#[cfg(not(feature = "backend-rust"))]
+struct Rng(u32);
+
+#[cfg(not(feature = "backend-rust"))]
+impl Rng {
+    fn next(&mut self) -> u32 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 17;
+        self.0 ^= self.0 << 5;
+        self.0
+    }
+
+    fn bytes(&mut self, len: usize, alphabet: u32) -> Vec<u8> {
+        (0..len).map(|_| (self.next() % alphabet) as u8).collect()
+    }
+}
+
+#[cfg(not(feature = "backend-rust"))]
+fn inputs() -> Vec<Vec<u8>> {
+    let mut rng = Rng(0x2545_f491);
+    let mut inputs = vec![
+        vec![],
+        vec![0],
+        b"abcdabcdabcdabcd".to_vec(),
+        vec![0; 1 << 20],
+        (0..100_000u32).map(|i| (i % 255) as u8).collect(),
+    ];
+    for len in [1, 15, 16, 100, 4096, 65535, 65536, 65537, 300_000] {
+        // random, and the small alphabet for the matches.
+        inputs.push(rng.bytes(len, 256));
+        inputs.push(rng.bytes(len, 4));
+    }
+    inputs
+}
+
+#[cfg(not(feature = "backend-rust"))]
+fn assert_same_uncompress(c: &[u8]) {
+    assert_eq!(
+        ffi::validate_compressed_buffer(c),
+        rust::validate_compressed_buffer(c),
+        "validate {c:02x?}",
+    );
+    assert_eq!(
+        ffi::uncompressed_length(c).ok(),
+        rust::uncompressed_length(c).ok(),
+        "uncompressed_length {c:02x?}",
+    );
+    // bounded buffer, not to trust the length in the adversarial input.
+    let mut expected = vec![0; 1 << 16];
+    let mut got = vec![0; 1 << 16];
+    let expected = ffi::uncompress_into(c, &mut expected).map(|n| expected[..n].to_vec());
+    let got = rust::uncompress_into(c, &mut got).map(|n| got[..n].to_vec());
+    assert_eq!(expected.ok(), got.ok(), "uncompress {c:02x?}");
+}
+
+#[test]
+fn format_vectors() {
+    let abcd = b"abcd".to_vec();
+    // 200 zeros: the 2 bytes varint length, a literal and the copies of
+    // the maximum length.
+    let zeros = [
+        &[200, 1, 0, 0][..],
+        &[0xfe, 1, 0].repeat(3),
+        &[6 << 2 | 0b10, 1, 0],
+    ]
+    .concat();
+    let valid: &[(&[u8], Vec<u8>)] = &[
+        (&[0], vec![]),
+        // literal.
+        (&[4, 3 << 2, b'a', b'b', b'c', b'd'], abcd.clone()),
+        // copy with the 1, 2 and 4 bytes offset.
+        (
+            &[12, 3 << 2, b'a', b'b', b'c', b'd', 4 << 2 | 0b01, 4],
+            abcd.repeat(3),
+        ),
+        (
+            &[16, 3 << 2, b'a', b'b', b'c', b'd', 11 << 2 | 0b10, 4, 0],
+            abcd.repeat(4),
+        ),
+        (
+            &[8, 3 << 2, b'a', b'b', b'c', b'd', 3 << 2 | 0b11, 4, 0, 0, 0],
+            abcd.repeat(2),
+        ),
+        // overlapping copy.
+        (&[10, 0, b'a', 5 << 2 | 0b01, 1], vec![b'a'; 10]),
+        (&zeros, vec![0; 200]),
+    ];
+    let invalid: &[&[u8]] = &[
+        &[],
+        &[0x80],
+        &[0xff, 0xff, 0xff, 0xff, 0x1f],
+        &[4, 0b01, 1],
+        &[8, 0, b'a', 0b01, 0],
+        &[2, 0, b'a', 0b01, 1],
+        &[4, 3 << 2, b'a'],
+        &[100, 63 << 2, 1],
+        &[5, 0, b'a'],
+    ];
+    for (c, d) in valid {
+        assert!(ffi::validate_compressed_buffer(c), "{c:02x?}");
+        assert!(rust::validate_compressed_buffer(c), "{c:02x?}");
+        assert_eq!(&ffi::uncompress(c).unwrap(), d, "{c:02x?}");
+        assert_eq!(&rust::uncompress(c).unwrap(), d, "{c:02x?}");
+    }
+    for c in invalid {
+        assert!(!ffi::validate_compressed_buffer(c), "{c:02x?}");
+        assert!(!rust::validate_compressed_buffer(c), "{c:02x?}");
+        assert!(ffi::uncompress(c).is_err(), "{c:02x?}");
+        assert!(rust::uncompress(c).is_err(), "{c:02x?}");
+    }
+}
+
+#[cfg(not(feature = "backend-rust"))]
+#[test]
+fn max_compressed_length() {
+    for len in [0, 1, 100, 1 << 16, 1 << 30] {
+        assert_eq!(
+            ffi::max_compressed_length(len),
+            rust::max_compressed_length(len)
+        );
+    }
+}
+
+#[cfg(not(feature = "backend-rust"))]
+#[test]
+fn round_trip_across_backends() {
+    for d in inputs() {
+        let c = ffi::compress(&d).unwrap();
+        let r = rust::compress(&d).unwrap();
+        assert_eq!(rust::uncompress(&c).unwrap(), d);
+        assert_eq!(ffi::uncompress(&r).unwrap(), d);
+        assert!(ffi::validate_compressed_buffer(&r));
+        assert!(rust::validate_compressed_buffer(&c));
+    }
+}
+
+#[cfg(not(feature = "backend-rust"))]
+#[test]
+fn random_compressed() {
+    let mut rng = Rng(0xdead_beef);
+    for _ in 0..10_000 {
+        let len = (rng.next() % 64) as usize;
+        let mut c = rng.bytes(len, 256);
+        // keep the uncompressed length small most of the time.
+        if let Some(first) = c.first_mut() {
+            *first &= 0x7f;
+        }
+        assert_same_uncompress(&c);
+    }
+}
+
+#[cfg(not(feature = "backend-rust"))]
+#[test]
+fn mutated_compressed() {
+    let mut rng = Rng(0x1234_5678);
+    for d in inputs().iter().filter(|d| d.len() < 1 << 16) {
+        let c = ffi::compress(d).unwrap();
+        for _ in 0..200 {
+            let mut m = c.clone();
+            match rng.next() % 3 {
+                0 => {
+                    let i = rng.next() as usize % m.len();
+                    m[i] ^= 1 << (rng.next() % 8);
+                }
+                1 => m.truncate(rng.next() as usize % m.len()),
+                _ => m.push(rng.next() as u8),
+            }
+            assert_same_uncompress(&m);
+        }
+    }
+}
+
+#[cfg(not(feature = "backend-rust"))]
+#[test]
+fn adversarial() {
+    let cases: &[&[u8]] = &[
+        // truncated varint and the overlong varint.
+        &[0x80],
+        &[0xff, 0xff, 0xff, 0xff, 0x1f],
+        // copy before the start of the output.
+        &[4, 0b01, 1],
+        &[8, 0, b'a', 0b01, 2],
+        // copy with the zero offset.
+        &[8, 0, b'a', 0b01, 0],
+        &[8, 0, b'a', 0b10, 0, 0],
+        // copy beyond the uncompressed length.
+        &[2, 0, b'a', 0b01, 1],
+        // literal beyond the input and the uncompressed length.
+        &[4, 3 << 2, b'a'],
+        &[1, 1 << 2, b'a', b'b'],
+        // long literal length bytes missing.
+        &[100, 63 << 2, 1],
+        // copy with the 4 bytes offset.
+        &[8, 3 << 2, b'a', b'b', b'c', b'd', 3 << 2 | 0b11, 4, 0, 0, 0],
+        // output shorter than the uncompressed length.
+        &[5, 0, b'a'],
+    ];
+    for c in cases {
+        assert_same_uncompress(c);
+    }
+}
@@ -0,0 +1,65 @@
+//! Boxed [`Tree`] against the [`ArenaTree`].
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use tree2::arena::ArenaTree;
+use tree2::Tree;
+
+// pseudo random data with xorshift, to avoid the degenerated trees.
+fn data(len: usize) -> Vec<u32> {
+    let mut seed = 0x2545_f491_u32;
+    (0..len)
+        .map(|_| {
+            seed ^= seed << 13;
+            seed ^= seed >> 17;
+            seed ^= seed << 5;
+            seed
+        })
+        .collect()
+}
+
+fn insert(c: &mut Criterion) {
+    let mut group = c.benchmark_group("insert");
+    for len in [1_000, 100_000] {
+        let data = data(len);
+        group.bench_with_input(BenchmarkId::new("Tree", len), &data, |b, data| {
+            b.iter(|| {
+                let mut tree = Tree::new();
+                for x in data {
+                    tree.insert(*x);
+                }
+                tree
+            })
+        });
+        group.bench_with_input(BenchmarkId::new("ArenaTree", len), &data, |b, data| {
+            b.iter(|| {
+                let mut tree = ArenaTree::new();
+                for x in data {
+                    tree.insert(*x);
+                }
+                tree
+            })
+        });
+    }
+    group.finish();
+}
+
+fn iter(c: &mut Criterion) {
+    let mut group = c.benchmark_group("iter");
+    for len in [1_000, 100_000] {
+        let mut tree = Tree::new();
+        let mut arena = ArenaTree::new();
+        for x in data(len) {
+            tree.insert(x);
+            arena.insert(x);
+        }
+        group.bench_function(BenchmarkId::new("Tree", len), |b| {
+            b.iter(|| black_box(tree.iter().fold(0u32, |sum, x| sum.wrapping_add(*x))))
+        });
+        group.bench_function(BenchmarkId::new("ArenaTree", len), |b| {
+            b.iter(|| black_box(arena.iter().fold(0u32, |sum, x| sum.wrapping_add(*x))))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, insert, iter);
+criterion_main!(benches);
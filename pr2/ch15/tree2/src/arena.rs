@@ -0,0 +1,314 @@
+//! Arena allocated variant of the [`Tree`].
+//!
+//! All the nodes live in a single `Vec` and are linked by the index,
+//! instead of one heap allocation per node.  The slots of the removed
+//! nodes are chained in the free list and reused by the next insertion.
+//!
+//! [`Tree`]: super::Tree
+use std::cmp::{Ord, Ordering};
+use std::mem;
+use std::num::NonZeroU32;
+
+pub struct ArenaTree<T: Ord> {
+    slots: Vec<Slot<T>>,
+    root: Link,
+    free: Link,
+    len: usize,
+}
+
+impl<T: Ord> Default for ArenaTree<T> {
+    fn default() -> Self {
+        Self {
+            slots: Vec::default(),
+            root: None,
+            free: None,
+            len: 0,
+        }
+    }
+}
+
+impl<T: Ord> ArenaTree<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn contains(&self, data: &T) -> bool {
+        self.find(data).1.is_some()
+    }
+
+    pub fn insert(&mut self, data: T) -> bool {
+        let mut parent = Parent::Root;
+        while let Some(index) = self.link(parent) {
+            let node = self.node(index);
+            parent = match data.cmp(&node.data) {
+                Ordering::Less => Parent::Left(index),
+                Ordering::Greater => Parent::Right(index),
+                Ordering::Equal => return false,
+            };
+        }
+        let index = self.alloc(Node::new(data));
+        *self.link_mut(parent) = Some(index);
+        self.len += 1;
+        true
+    }
+
+    pub fn remove(&mut self, data: &T) -> Option<T> {
+        let (parent, index) = match self.find(data) {
+            (parent, Some(index)) => (parent, index),
+            (_, None) => return None,
+        };
+        let node = self.node(index);
+        let (left, right) = (node.left, node.right);
+        let replacement = match (left, right) {
+            (None, child) | (child, None) => child,
+            (Some(_), Some(right)) => {
+                // replace it with the left most node of the right subtree.
+                let mut successor_parent = Parent::Right(index);
+                let mut successor = right;
+                while let Some(left) = self.node(successor).left {
+                    successor_parent = Parent::Left(successor);
+                    successor = left;
+                }
+                *self.link_mut(successor_parent) = self.node(successor).right;
+                // read the right link again, as it could be updated above.
+                let right = self.node(index).right;
+                let node = self.node_mut(successor);
+                node.left = left;
+                node.right = right;
+                Some(successor)
+            }
+        };
+        *self.link_mut(parent) = replacement;
+        self.len -= 1;
+        Some(self.dealloc(index))
+    }
+
+    pub fn iter(&self) -> DepthFirstIter<'_, T> {
+        let mut iter = DepthFirstIter {
+            slots: &self.slots,
+            unvisited: Vec::default(),
+        };
+        iter.push_left_edge(self.root);
+        iter
+    }
+
+    // returns the parent and the index of the node holding the data.
+    fn find(&self, data: &T) -> (Parent, Link) {
+        let mut parent = Parent::Root;
+        while let Some(index) = self.link(parent) {
+            let node = self.node(index);
+            parent = match data.cmp(&node.data) {
+                Ordering::Less => Parent::Left(index),
+                Ordering::Greater => Parent::Right(index),
+                Ordering::Equal => return (parent, Some(index)),
+            };
+        }
+        (parent, None)
+    }
+
+    fn alloc(&mut self, node: Node<T>) -> NonZeroU32 {
+        match self.free {
+            Some(index) => {
+                let slot = &mut self.slots[slot_index(index)];
+                self.free = match mem::replace(slot, Slot::Occupied(node)) {
+                    Slot::Vacant(next) => next,
+                    Slot::Occupied(_) => unreachable!("occupied slot in the free list"),
+                };
+                index
+            }
+            None => {
+                self.slots.push(Slot::Occupied(node));
+                u32::try_from(self.slots.len())
+                    .ok()
+                    .and_then(NonZeroU32::new)
+                    .expect("too many nodes for ArenaTree")
+            }
+        }
+    }
+
+    fn dealloc(&mut self, index: NonZeroU32) -> T {
+        let slot = &mut self.slots[slot_index(index)];
+        let data = match mem::replace(slot, Slot::Vacant(self.free)) {
+            Slot::Occupied(node) => node.data,
+            Slot::Vacant(_) => unreachable!("double free of the slot"),
+        };
+        self.free = Some(index);
+        data
+    }
+
+    fn link(&self, parent: Parent) -> Link {
+        match parent {
+            Parent::Root => self.root,
+            Parent::Left(index) => self.node(index).left,
+            Parent::Right(index) => self.node(index).right,
+        }
+    }
+
+    fn link_mut(&mut self, parent: Parent) -> &mut Link {
+        match parent {
+            Parent::Root => &mut self.root,
+            Parent::Left(index) => &mut self.node_mut(index).left,
+            Parent::Right(index) => &mut self.node_mut(index).right,
+        }
+    }
+
+    fn node(&self, index: NonZeroU32) -> &Node<T> {
+        self.slots[slot_index(index)].node()
+    }
+
+    fn node_mut(&mut self, index: NonZeroU32) -> &mut Node<T> {
+        match &mut self.slots[slot_index(index)] {
+            Slot::Occupied(node) => node,
+            Slot::Vacant(_) => unreachable!("link to the vacant slot"),
+        }
+    }
+}
+
+pub struct DepthFirstIter<'a, T: 'a + Ord> {
+    slots: &'a [Slot<T>],
+    unvisited: Vec<&'a Node<T>>,
+}
+
+impl<'a, T: 'a + Ord> Iterator for DepthFirstIter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        self.unvisited.pop().map(|node| {
+            self.push_left_edge(node.right);
+            &node.data
+        })
+    }
+}
+
+impl<'a, T: 'a + Ord> DepthFirstIter<'a, T> {
+    fn push_left_edge(&mut self, mut link: Link) {
+        while let Some(index) = link {
+            let node = self.slots[slot_index(index)].node();
+            link = node.left;
+            self.unvisited.push(node);
+        }
+    }
+}
+
+// Index link, which is one-based to make the `Option` free.
+type Link = Option<NonZeroU32>;
+
+// The parent link of the node.
+#[derive(Clone, Copy)]
+enum Parent {
+    Root,
+    Left(NonZeroU32),
+    Right(NonZeroU32),
+}
+
+enum Slot<T> {
+    Occupied(Node<T>),
+    Vacant(Link),
+}
+
+impl<T> Slot<T> {
+    fn node(&self) -> &Node<T> {
+        match self {
+            Slot::Occupied(node) => node,
+            Slot::Vacant(_) => unreachable!("link to the vacant slot"),
+        }
+    }
+}
+
+struct Node<T> {
+    data: T,
+    left: Link,
+    right: Link,
+}
+
+impl<T> Node<T> {
+    fn new(data: T) -> Self {
+        Self {
+            data,
+            left: None,
+            right: None,
+        }
+    }
+}
+
+fn slot_index(index: NonZeroU32) -> usize {
+    index.get() as usize - 1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ArenaTree;
+    use std::collections::BTreeSet;
+
+    #[test]
+    fn insert() {
+        let mut tree = ArenaTree::new();
+        assert!(tree.insert(2));
+        assert!(tree.insert(1));
+        assert!(tree.insert(3));
+        assert!(!tree.insert(1));
+        assert_eq!(tree.len(), 3);
+        assert!(tree.iter().copied().eq([1, 2, 3]));
+    }
+
+    #[test]
+    fn remove() {
+        let mut tree = ArenaTree::new();
+        for data in [50, 20, 80, 10, 30, 25, 90, 85, 70] {
+            tree.insert(data);
+        }
+        // leaf, one child, two children and the root.
+        assert_eq!(tree.remove(&10), Some(10));
+        assert_eq!(tree.remove(&90), Some(90));
+        assert_eq!(tree.remove(&20), Some(20));
+        assert_eq!(tree.remove(&50), Some(50));
+        assert_eq!(tree.remove(&50), None);
+        assert!(tree.iter().copied().eq([25, 30, 70, 80, 85]));
+        assert_eq!(tree.len(), 5);
+    }
+
+    #[test]
+    fn remove_reuses_slots() {
+        let mut tree = ArenaTree::new();
+        for data in 0..8 {
+            tree.insert(data);
+        }
+        for data in 0..4 {
+            tree.remove(&data);
+        }
+        for data in 10..14 {
+            tree.insert(data);
+        }
+        assert_eq!(tree.slots.len(), 8);
+        assert!(tree.iter().copied().eq((4..8).chain(10..14)));
+    }
+
+    #[test]
+    fn against_btreeset() {
+        let mut tree = ArenaTree::new();
+        let mut set = BTreeSet::new();
+        let mut seed = 0x2545_f491_u32;
+        for _ in 0..10_000 {
+            // xorshift
+            seed ^= seed << 13;
+            seed ^= seed >> 17;
+            seed ^= seed << 5;
+            let data = seed % 512;
+            if seed.is_multiple_of(3) {
+                assert_eq!(tree.remove(&data), set.take(&data));
+            } else {
+                assert_eq!(tree.insert(data), set.insert(data));
+            }
+            assert_eq!(tree.len(), set.len());
+        }
+        assert!(tree.iter().eq(set.iter()));
+    }
+}
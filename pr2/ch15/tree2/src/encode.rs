@@ -0,0 +1,116 @@
+//! Compact, shape preserving binary encoding of the [`Tree`].
+//!
+//! The tree is written in pre-order, one tag byte per node followed by
+//! the node data.  The tag tells if the node has the left and/or the
+//! right child, which is all it takes to rebuild the exact same shape.
+//! The encoding is prefixed by a single byte telling if the tree is
+//! empty or not.
+use super::{Node, Tree};
+use std::io::{self, Read, Write};
+
+const HAS_LEFT: u8 = 0b01;
+const HAS_RIGHT: u8 = 0b10;
+
+impl<T: Ord> Tree<T> {
+    /// Encodes the tree into `writer`, with `encode_data` to write
+    /// each node data.
+    pub fn encode<W, F>(&self, mut writer: W, mut encode_data: F) -> io::Result<()>
+    where
+        W: Write,
+        F: FnMut(&T, &mut W) -> io::Result<()>,
+    {
+        writer.write_all(&[self.0.is_some() as u8])?;
+        let mut unvisited: Vec<&Node<T>> = self.0.as_deref().into_iter().collect();
+        while let Some(node) = unvisited.pop() {
+            writer.write_all(&[tag(node)])?;
+            encode_data(&node.data, &mut writer)?;
+            unvisited.extend(node.right.as_deref());
+            unvisited.extend(node.left.as_deref());
+        }
+        Ok(())
+    }
+
+    /// Decodes the tree encoded by [`Tree::encode`] out of `reader`,
+    /// with `decode_data` to read each node data.
+    ///
+    /// It returns the [`io::ErrorKind::InvalidData`] error in case the
+    /// decoded tree is not a valid binary search tree.
+    pub fn decode<R, F>(mut reader: R, mut decode_data: F) -> io::Result<Self>
+    where
+        R: Read,
+        F: FnMut(&mut R) -> io::Result<T>,
+    {
+        // read all the nodes in pre-order first.
+        let mut nodes = vec![];
+        let mut pending = read_u8(&mut reader)? as usize;
+        if pending > 1 {
+            return Err(invalid_data("invalid tree header"));
+        }
+        while pending > 0 {
+            let tag = read_u8(&mut reader)?;
+            if tag & !(HAS_LEFT | HAS_RIGHT) != 0 {
+                return Err(invalid_data("invalid node tag"));
+            }
+            let data = decode_data(&mut reader)?;
+            pending += (tag & HAS_LEFT != 0) as usize + (tag & HAS_RIGHT != 0) as usize;
+            pending -= 1;
+            nodes.push((tag, data));
+        }
+
+        // then build the tree bottom up by walking the nodes in the
+        // reverse pre-order, e.g. right subtree, left subtree and the
+        // node itself, so that both subtrees are ready on the stack.
+        let mut subtrees: Vec<Box<Node<T>>> = vec![];
+        for (tag, data) in nodes.into_iter().rev() {
+            let mut node = Node::new(data);
+            if tag & HAS_LEFT != 0 {
+                node.left = subtrees.pop();
+            }
+            if tag & HAS_RIGHT != 0 {
+                node.right = subtrees.pop();
+            }
+            subtrees.push(Box::new(node));
+        }
+        let tree = Self(subtrees.pop());
+        if !is_ascending(&tree) {
+            return Err(invalid_data("tree is not in order"));
+        }
+        Ok(tree)
+    }
+}
+
+fn tag<T: Ord>(node: &Node<T>) -> u8 {
+    let mut tag = 0;
+    if node.left.is_some() {
+        tag |= HAS_LEFT;
+    }
+    if node.right.is_some() {
+        tag |= HAS_RIGHT;
+    }
+    tag
+}
+
+fn is_ascending<T: Ord>(tree: &Tree<T>) -> bool {
+    let mut iter = tree.iter();
+    let mut prev = match iter.next() {
+        None => return true,
+        Some(data) => data,
+    };
+    for data in iter {
+        if prev >= data {
+            return false;
+        }
+        prev = data;
+    }
+    true
+}
+
+fn read_u8<R: Read>(reader: &mut R) -> io::Result<u8> {
+    let mut buf = [0u8; 1];
+    reader.read_exact(&mut buf)?;
+    Ok(buf[0])
+}
+
+fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
@@ -1,5 +1,10 @@
 use std::cmp::{Ord, Ordering};
 
+pub mod arena;
+mod encode;
+#[cfg(feature = "serde")]
+mod serde_impl;
+
 pub struct Tree<T: Ord>(Link<T>);
 
 impl<T: Ord> Default for Tree<T> {
@@ -26,6 +31,17 @@ impl<T: Ord> Tree<T> {
         true
     }
 
+    /// Builds a perfectly balanced tree out of the strictly ascending
+    /// `items` in O(n).  Returns `None` if `items` is not strictly
+    /// ascending.
+    pub fn from_sorted(items: Vec<T>) -> Option<Self> {
+        if items.windows(2).any(|pair| pair[0] >= pair[1]) {
+            return None;
+        }
+        let len = items.len();
+        Some(Self(build_balanced(&mut items.into_iter(), len)))
+    }
+
     pub fn iter(&self) -> DepthFirstIter<T> {
         let mut iter = DepthFirstIter::default();
         iter.push_left_edge(&self.0);
@@ -67,6 +83,22 @@ impl<'a, T: 'a + Ord> DepthFirstIter<'a, T> {
 
 type Link<T> = Option<Box<Node<T>>>;
 
+// Builds the balanced subtree of the next `len` items in order,
+// left subtree first so that the iterator is consumed in order.
+fn build_balanced<T, I>(items: &mut I, len: usize) -> Link<T>
+where
+    T: Ord,
+    I: Iterator<Item = T>,
+{
+    if len == 0 {
+        return None;
+    }
+    let left = build_balanced(items, len / 2);
+    let data = items.next()?;
+    let right = build_balanced(items, len - len / 2 - 1);
+    Some(Box::new(Node { data, left, right }))
+}
+
 struct Node<T: Ord> {
     data: T,
     left: Link<T>,
@@ -82,3 +114,120 @@ impl<T: Ord> Node<T> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{Link, Tree};
+    use std::io::{Read, Write};
+
+    // pre-order list of the data with the presence of both children.
+    fn shape<T: Ord + Copy>(tree: &Tree<T>) -> Vec<(T, bool, bool)> {
+        let mut shape = vec![];
+        let mut unvisited: Vec<&Link<T>> = vec![&tree.0];
+        while let Some(link) = unvisited.pop() {
+            if let Some(node) = link {
+                shape.push((node.data, node.left.is_some(), node.right.is_some()));
+                unvisited.push(&node.right);
+                unvisited.push(&node.left);
+            }
+        }
+        shape
+    }
+
+    fn encode(tree: &Tree<u32>) -> Vec<u8> {
+        let mut buf = vec![];
+        tree.encode(&mut buf, |data, w| w.write_all(&data.to_le_bytes()))
+            .unwrap();
+        buf
+    }
+
+    fn decode(buf: &[u8]) -> std::io::Result<Tree<u32>> {
+        Tree::decode(buf, |r| {
+            let mut data = [0u8; 4];
+            r.read_exact(&mut data)?;
+            Ok(u32::from_le_bytes(data))
+        })
+    }
+
+    #[test]
+    fn from_sorted() {
+        let tree = Tree::from_sorted((1..=7).collect()).unwrap();
+        assert_eq!(
+            shape(&tree),
+            vec![
+                (4, true, true),
+                (2, true, true),
+                (1, false, false),
+                (3, false, false),
+                (6, true, true),
+                (5, false, false),
+                (7, false, false),
+            ],
+        );
+        assert!(tree.iter().copied().eq(1..=7));
+    }
+
+    #[test]
+    fn from_sorted_not_in_order() {
+        assert!(Tree::from_sorted(vec![1, 3, 2]).is_none());
+        assert!(Tree::from_sorted(vec![1, 1]).is_none());
+        assert!(Tree::<u32>::from_sorted(vec![]).unwrap().0.is_none());
+    }
+
+    #[test]
+    fn encode_decode() {
+        let mut tree = Tree::new();
+        for data in [50, 20, 80, 10, 30, 25, 90, 85] {
+            tree.insert(data);
+        }
+        let buf = encode(&tree);
+        assert_eq!(buf.len(), 1 + 8 * 5);
+        let decoded = decode(&buf).unwrap();
+        assert_eq!(shape(&decoded), shape(&tree));
+        assert_eq!(encode(&decoded), buf);
+    }
+
+    #[test]
+    fn encode_decode_degenerated() {
+        let mut tree = Tree::new();
+        for data in (0..10_000).rev() {
+            tree.insert(data);
+        }
+        let decoded = decode(&encode(&tree)).unwrap();
+        assert_eq!(shape(&decoded), shape(&tree));
+    }
+
+    #[test]
+    fn encode_decode_empty() {
+        let tree = Tree::new();
+        assert_eq!(encode(&tree), [0]);
+        assert!(decode(&[0]).unwrap().0.is_none());
+    }
+
+    #[test]
+    fn decode_invalid() {
+        // out of order.
+        let buf = [1, 0b01, 1, 0, 0, 0, 0, 2, 0, 0, 0];
+        assert!(decode(&buf).is_err());
+        // truncated.
+        let buf = [1, 0b11, 1, 0, 0, 0];
+        assert!(decode(&buf).is_err());
+        // invalid tag.
+        let buf = [1, 0b100, 1, 0, 0, 0];
+        assert!(decode(&buf).is_err());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_json() {
+        let mut tree = Tree::new();
+        for data in [3, 1, 2] {
+            tree.insert(data);
+        }
+        let json = serde_json::to_string(&tree).unwrap();
+        assert_eq!(json, "[1,2,3]");
+        let tree: Tree<u32> = serde_json::from_str("[5,3,4,3,1]").unwrap();
+        assert!(tree.iter().copied().eq([1, 3, 4, 5]));
+        assert_eq!(shape(&tree)[0], (4, true, true));
+    }
+}
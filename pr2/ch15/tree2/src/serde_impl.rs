@@ -0,0 +1,22 @@
+//! [serde] support of the [`Tree`], as the ordered sequence of the data.
+//!
+//! [serde]: https://serde.rs
+use super::Tree;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+impl<T: Ord + Serialize> Serialize for Tree<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.iter())
+    }
+}
+
+impl<'de, T: Ord + Deserialize<'de>> Deserialize<'de> for Tree<T> {
+    /// Deserializes the sequence into the balanced tree.  The sequence
+    /// doesn't need to be in order, but it's O(n) when it is.
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let mut items = Vec::<T>::deserialize(deserializer)?;
+        items.sort();
+        items.dedup();
+        Ok(Self::from_sorted(items).unwrap_or_default())
+    }
+}
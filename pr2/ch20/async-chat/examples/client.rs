@@ -2,7 +2,9 @@ use futures_lite::future::{self, FutureExt};
 use futures_lite::io::{AsyncBufReadExt, BufReader};
 use futures_lite::stream::StreamExt;
 
-use async_chat::{recv_as_json, send_as_json, Request, Response};
+use async_chat::{
+    handshake, recv_packets, send_packet, AnyCodec, Request, Response, DEFAULT_MAX_FRAME_LEN,
+};
 use async_net::AsyncToSocketAddrs as ToSocketAddrs;
 use async_net::TcpStream;
 use async_std::io::stdin;
@@ -11,40 +13,64 @@ const ADDR: &str = "localhost:8080";
 
 fn main() {
     let addr = std::env::args().nth(1).unwrap_or_else(|| ADDR.to_string());
+    let codec = std::env::args()
+        .nth(2)
+        .unwrap_or_else(|| "json".to_string());
 
-    if let Err(e) = future::block_on(client(addr)) {
+    if let Err(e) = future::block_on(client(addr, codec)) {
         eprintln!("error: {e}");
     }
 }
 
-async fn client(addr: impl ToSocketAddrs) -> eyre::Result<()> {
-    let s = TcpStream::connect(addr).await?;
-    receiver(s.clone()).race(sender(s)).await?;
+async fn client(addr: impl ToSocketAddrs, codec: String) -> eyre::Result<()> {
+    let codec = AnyCodec::from_name(&codec, DEFAULT_MAX_FRAME_LEN)?;
+    let mut s = TcpStream::connect(addr).await?;
+    handshake(&mut s, &codec).await?;
+    receiver(s.clone(), codec).race(sender(s, codec)).await?;
     Ok(())
 }
 
-async fn sender(mut tx: TcpStream) -> eyre::Result<()> {
+async fn sender(mut tx: TcpStream, codec: AnyCodec) -> eyre::Result<()> {
     let reader = BufReader::new(stdin());
     let mut lines = reader.lines();
 
     while let Some(line) = lines.try_next().await? {
         let req = Request::try_from(line)?;
-        send_as_json(&mut tx, &req).await?;
+        send_packet(&codec, &mut tx, &req).await?;
     }
     Ok(())
 }
 
-async fn receiver(rx: TcpStream) -> eyre::Result<()> {
-    let reader = BufReader::new(rx);
-    let mut stream = recv_as_json(reader);
+async fn receiver(rx: TcpStream, codec: AnyCodec) -> eyre::Result<()> {
+    let mut stream = recv_packets(codec, rx);
 
     while let Some(resp) = stream.try_next().await? {
         match resp {
+            Response::Welcome { name } => {
+                println!("logged in as {name}");
+            }
             Response::Message {
                 group_name,
+                seq,
+                sender,
                 message,
             } => {
-                println!("{group_name}: {message:?}");
+                println!("{group_name} #{seq}: {sender}: {message:?}");
+            }
+            Response::Lagged {
+                group_name,
+                skipped,
+            } => {
+                println!("{group_name}: {skipped} messages missed");
+            }
+            Response::Private { sender, message } => {
+                println!("{sender} (private): {message:?}");
+            }
+            Response::Groups { group_names } => {
+                println!("groups: {group_names:?}");
+            }
+            Response::Members { group_name, names } => {
+                println!("{group_name}: members: {names:?}");
             }
             Response::Error(e) => {
                 println!("error: {e}");
@@ -0,0 +1,376 @@
+//! Wire codecs, negotiated at the connection start.
+//!
+//! The client sends the handshake line `async-chat <codec>`, and the
+//! server echoes it back to accept, before any packet:
+//!
+//! - `json`: newline-delimited JSON, as [`send_as_json`].
+//! - `length-prefixed`: u32 BE length, then the CBOR payload, so that
+//!   the frame is bounded before reading it.
+//!
+//! Either rejects the frame larger than its `max_frame_len`.
+//!
+//! [`send_as_json`]: crate::send_as_json
+use eyre::eyre;
+use futures_lite::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use futures_lite::stream::{self, Stream};
+use serde::{de::DeserializeOwned, Serialize};
+
+/// Default maximum frame length, in bytes.
+pub const DEFAULT_MAX_FRAME_LEN: usize = 64 * 1024;
+
+const HANDSHAKE: &str = "async-chat";
+// longer handshake lines are rejected.
+const MAX_HANDSHAKE_LEN: usize = 64;
+const READ_LEN: usize = 4096;
+
+/// Framing of the packets on the wire.
+pub trait Codec {
+    /// Appends the frame of `packet` to `buf`.
+    fn encode<P: Serialize>(&self, packet: &P, buf: &mut Vec<u8>) -> eyre::Result<()>;
+
+    /// Decodes the frame at the start of `buf`, and returns the packet
+    /// with the frame length, or `None` if the frame is not complete yet.
+    fn decode<P: DeserializeOwned>(&self, buf: &[u8]) -> eyre::Result<Option<(P, usize)>>;
+}
+
+/// Newline-delimited JSON.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct JsonLines {
+    /// Including the newline.
+    pub max_frame_len: usize,
+}
+
+impl Default for JsonLines {
+    fn default() -> Self {
+        Self {
+            max_frame_len: DEFAULT_MAX_FRAME_LEN,
+        }
+    }
+}
+
+impl Codec for JsonLines {
+    fn encode<P: Serialize>(&self, packet: &P, buf: &mut Vec<u8>) -> eyre::Result<()> {
+        let start = buf.len();
+        // JSON escapes the newlines in the strings.
+        let result = serde_json::to_writer(&mut *buf, packet)
+            .map_err(eyre::Report::from)
+            .and_then(|_| {
+                buf.push(b'\n');
+                check_len(buf.len() - start, self.max_frame_len)
+            });
+        if result.is_err() {
+            // leaves no partial frame.
+            buf.truncate(start);
+        }
+        result
+    }
+
+    fn decode<P: DeserializeOwned>(&self, buf: &[u8]) -> eyre::Result<Option<(P, usize)>> {
+        let limit = buf.len().min(self.max_frame_len);
+        match buf[..limit].iter().position(|&b| b == b'\n') {
+            Some(end) => Ok(Some((serde_json::from_slice(&buf[..end])?, end + 1))),
+            None if buf.len() >= self.max_frame_len => {
+                let max = self.max_frame_len;
+                Err(eyre!("frame too large: over {max} bytes"))
+            }
+            None => Ok(None),
+        }
+    }
+}
+
+/// u32 BE length, then the CBOR payload.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct LengthPrefixed {
+    /// Of the payload, without the length.
+    pub max_frame_len: usize,
+}
+
+impl Default for LengthPrefixed {
+    fn default() -> Self {
+        Self {
+            max_frame_len: DEFAULT_MAX_FRAME_LEN,
+        }
+    }
+}
+
+impl Codec for LengthPrefixed {
+    fn encode<P: Serialize>(&self, packet: &P, buf: &mut Vec<u8>) -> eyre::Result<()> {
+        let mut payload = vec![];
+        ciborium::into_writer(packet, &mut payload)?;
+        check_len(payload.len(), self.max_frame_len)?;
+        let len = u32::try_from(payload.len())?;
+        buf.extend(len.to_be_bytes());
+        buf.extend(payload);
+        Ok(())
+    }
+
+    fn decode<P: DeserializeOwned>(&self, buf: &[u8]) -> eyre::Result<Option<(P, usize)>> {
+        let Some(header) = buf.get(..4) else {
+            return Ok(None);
+        };
+        let len = u32::from_be_bytes(header.try_into().unwrap()) as usize;
+        check_len(len, self.max_frame_len)?;
+        let Some(mut payload) = buf.get(4..4 + len) else {
+            return Ok(None);
+        };
+        let packet = ciborium::from_reader(&mut payload)?;
+        if !payload.is_empty() {
+            return Err(eyre!("trailing {} bytes in the frame", payload.len()));
+        }
+        Ok(Some((packet, 4 + len)))
+    }
+}
+
+/// Fails if `packet` is over `max_frame_len` in any codec.
+pub(crate) fn check_frame_len<P: Serialize>(packet: &P, max_frame_len: usize) -> eyre::Result<()> {
+    let codecs = [
+        AnyCodec::JsonLines(JsonLines { max_frame_len }),
+        AnyCodec::LengthPrefixed(LengthPrefixed { max_frame_len }),
+    ];
+    let mut buf = vec![];
+    for codec in codecs {
+        codec.encode(packet, &mut buf)?;
+        buf.clear();
+    }
+    Ok(())
+}
+
+fn check_len(len: usize, max_frame_len: usize) -> eyre::Result<()> {
+    if len > max_frame_len {
+        return Err(eyre!("frame too large: {len} > {max_frame_len} bytes"));
+    }
+    Ok(())
+}
+
+/// One of the codecs, chosen by the handshake.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum AnyCodec {
+    JsonLines(JsonLines),
+    LengthPrefixed(LengthPrefixed),
+}
+
+impl Default for AnyCodec {
+    fn default() -> Self {
+        Self::JsonLines(JsonLines::default())
+    }
+}
+
+impl AnyCodec {
+    /// Returns the codec by the handshake name, with `max_frame_len`.
+    pub fn from_name(name: &str, max_frame_len: usize) -> eyre::Result<Self> {
+        match name {
+            "json" => Ok(Self::JsonLines(JsonLines { max_frame_len })),
+            "length-prefixed" => Ok(Self::LengthPrefixed(LengthPrefixed { max_frame_len })),
+            _ => Err(eyre!("unsupported codec: {name:?}")),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::JsonLines(_) => "json",
+            Self::LengthPrefixed(_) => "length-prefixed",
+        }
+    }
+}
+
+impl Codec for AnyCodec {
+    fn encode<P: Serialize>(&self, packet: &P, buf: &mut Vec<u8>) -> eyre::Result<()> {
+        match self {
+            Self::JsonLines(codec) => codec.encode(packet, buf),
+            Self::LengthPrefixed(codec) => codec.encode(packet, buf),
+        }
+    }
+
+    fn decode<P: DeserializeOwned>(&self, buf: &[u8]) -> eyre::Result<Option<(P, usize)>> {
+        match self {
+            Self::JsonLines(codec) => codec.decode(buf),
+            Self::LengthPrefixed(codec) => codec.decode(buf),
+        }
+    }
+}
+
+/// Sends the handshake for `codec`, and waits for the server to accept
+/// it.
+pub async fn handshake<S>(s: &mut S, codec: &AnyCodec) -> eyre::Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let line = format!("{HANDSHAKE} {}", codec.name());
+    s.write_all(format!("{line}\n").as_bytes()).await?;
+    s.flush().await?;
+    let reply = read_line(s).await?;
+    if reply != line {
+        return Err(eyre!("handshake rejected: {reply:?}"));
+    }
+    Ok(())
+}
+
+/// Accepts the handshake from the client, and returns the codec with
+/// `max_frame_len`.  The unsupported one is rejected with the reason.
+pub(crate) async fn accept<S>(s: &mut S, max_frame_len: usize) -> eyre::Result<AnyCodec>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let line = read_line(s).await?;
+    let result = match line.split_once(' ') {
+        Some((HANDSHAKE, name)) => AnyCodec::from_name(name, max_frame_len),
+        _ => Err(eyre!("invalid handshake: {line:?}")),
+    };
+    let reply = match &result {
+        Ok(_) => line,
+        Err(e) => format!("{HANDSHAKE} error: {e}"),
+    };
+    s.write_all(format!("{reply}\n").as_bytes()).await?;
+    s.flush().await?;
+    result
+}
+
+// reads byte by byte, not to read ahead the packets.
+async fn read_line<S: AsyncRead + Unpin>(s: &mut S) -> eyre::Result<String> {
+    let mut line = vec![];
+    let mut byte = [0];
+    loop {
+        if s.read(&mut byte).await? == 0 {
+            return Err(eyre!("closed in the handshake"));
+        }
+        match byte[0] {
+            b'\n' => return Ok(String::from_utf8(line)?),
+            _ if line.len() == MAX_HANDSHAKE_LEN => return Err(eyre!("handshake too long")),
+            b => line.push(b),
+        }
+    }
+}
+
+/// Sends `packet` framed by `codec`.
+pub async fn send_packet<C, S, P>(codec: &C, tx: &mut S, packet: &P) -> eyre::Result<()>
+where
+    C: Codec,
+    S: AsyncWrite + Unpin,
+    P: Serialize,
+{
+    let mut buf = vec![];
+    codec.encode(packet, &mut buf)?;
+    tx.write_all(&buf).await?;
+    Ok(())
+}
+
+/// Returns the stream of the packets framed by `codec`, ending on the
+/// first error.
+pub fn recv_packets<C, S, P>(codec: C, rx: S) -> impl Stream<Item = eyre::Result<P>> + Unpin
+where
+    C: Codec,
+    S: AsyncRead + Unpin,
+    P: DeserializeOwned,
+{
+    // (codec, rx, unread bytes, done)
+    let state = (codec, rx, Vec::new(), false);
+    Box::pin(stream::unfold(
+        state,
+        |(codec, mut rx, mut buf, done)| async move {
+            if done {
+                return None;
+            }
+            let result = loop {
+                match codec.decode(&buf) {
+                    Ok(Some((packet, len))) => {
+                        buf.drain(..len);
+                        break Ok(packet);
+                    }
+                    Ok(None) => {}
+                    Err(e) => break Err(e),
+                }
+                let start = buf.len();
+                buf.resize(start + READ_LEN, 0);
+                match rx.read(&mut buf[start..]).await {
+                    Ok(0) if start == 0 => return None,
+                    Ok(0) => break Err(eyre!("closed in the middle of the frame")),
+                    Ok(n) => buf.truncate(start + n),
+                    Err(e) => break Err(e.into()),
+                }
+            };
+            let done = result.is_err();
+            Some((result, (codec, rx, buf, done)))
+        },
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AnyCodec, Codec, JsonLines, LengthPrefixed};
+
+    fn codecs(max_frame_len: usize) -> [AnyCodec; 2] {
+        [
+            AnyCodec::JsonLines(JsonLines { max_frame_len }),
+            AnyCodec::LengthPrefixed(LengthPrefixed { max_frame_len }),
+        ]
+    }
+
+    #[test]
+    fn round_trip() {
+        let packets = ["hello".to_string(), "two\nlines".to_string(), String::new()];
+        for codec in codecs(1024) {
+            let mut buf = vec![];
+            for packet in &packets {
+                codec.encode(packet, &mut buf).unwrap();
+            }
+            let mut decoded = vec![];
+            let mut pos = 0;
+            while let Some((packet, len)) = codec.decode::<String>(&buf[pos..]).unwrap() {
+                decoded.push(packet);
+                pos += len;
+            }
+            assert_eq!(pos, buf.len(), "{codec:?}");
+            assert_eq!(decoded, packets, "{codec:?}");
+        }
+    }
+
+    #[test]
+    fn partial_frame() {
+        for codec in codecs(1024) {
+            let mut buf = vec![];
+            codec.encode(&"hello", &mut buf).unwrap();
+            for end in 0..buf.len() {
+                assert!(codec.decode::<String>(&buf[..end]).unwrap().is_none());
+            }
+            assert!(codec.decode::<String>(&buf).unwrap().is_some());
+        }
+    }
+
+    #[test]
+    fn max_frame_len() {
+        for codec in codecs(8) {
+            let mut buf = vec![];
+            // "12345" is 8 bytes in JSON, with the quotes and the newline,
+            // and 6 in CBOR.
+            codec.encode(&"12345", &mut buf).unwrap();
+            let len = buf.len();
+            assert!(codec.encode(&"123456789", &mut buf).is_err(), "{codec:?}");
+            // no partial frame is left.
+            assert_eq!(buf.len(), len, "{codec:?}");
+        }
+        let json = JsonLines { max_frame_len: 8 };
+        assert!(json.decode::<String>(b"\"1234567").is_err());
+        assert!(json.decode::<String>(b"\"12345").unwrap().is_none());
+        let prefixed = LengthPrefixed { max_frame_len: 8 };
+        assert!(prefixed.decode::<String>(&[0, 0, 0, 9]).is_err());
+        assert!(prefixed.decode::<String>(&[0, 0, 0, 8]).unwrap().is_none());
+    }
+
+    #[test]
+    fn trailing_bytes() {
+        let codec = LengthPrefixed::default();
+        let mut buf = vec![];
+        codec.encode(&"hello", &mut buf).unwrap();
+        buf[3] += 1;
+        buf.push(0);
+        assert!(codec.decode::<String>(&buf).is_err());
+    }
+
+    #[test]
+    fn names() {
+        for codec in codecs(100) {
+            assert_eq!(AnyCodec::from_name(codec.name(), 100).unwrap(), codec);
+        }
+        assert!(AnyCodec::from_name("cbor", 100).is_err());
+    }
+}
@@ -0,0 +1,407 @@
+//! Append-only log of the group messages, to rebuild the history on
+//! restart.
+//!
+//! The log is a directory of the segments, each named by its first
+//! sequence number and rotated at [`Config::segment_size`].  A record is
+//!
+//! ```text
+//! len: u32 LE | crc32: u32 LE | JSON message, len bytes
+//! ```
+//!
+//! The torn record at the tail, written partly before the crash, is
+//! truncated on open.  The segments older than the last [`HISTORY_LEN`]
+//! messages are deleted, on open and on rotation.
+//!
+//! [`Config::segment_size`]: crate::Config::segment_size
+//! [`HISTORY_LEN`]: crate::HISTORY_LEN
+use super::{History, Message, HISTORY_LEN};
+use std::collections::VecDeque;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+const HEADER_LEN: usize = 8;
+
+#[derive(Debug)]
+pub(crate) struct Journal {
+    dir: PathBuf,
+    // first sequence number and path of each segment, the last being
+    // written.
+    segments: VecDeque<(u64, PathBuf)>,
+    segment: File,
+    // bytes of the valid records in the segment.
+    size: u64,
+    segment_size: u64,
+}
+
+impl Journal {
+    /// Opens the log in `dir`, creating it if missing, and returns the
+    /// history replayed from it.
+    pub(crate) fn open(dir: &Path, segment_size: u64) -> io::Result<(Self, History)> {
+        fs::create_dir_all(dir)?;
+        let mut segments = segments(dir)?;
+        // not to replay the segments to be deleted.
+        prune(&mut segments)?;
+        let mut history = History::default();
+        for (i, (_, path)) in segments.iter().enumerate() {
+            let valid = replay(path, &mut history)?;
+            if valid == fs::metadata(path)?.len() {
+                continue;
+            }
+            if i + 1 < segments.len() {
+                let e = format!("corrupt segment: {}", path.display());
+                return Err(io::Error::new(io::ErrorKind::InvalidData, e));
+            }
+            OpenOptions::new().write(true).open(path)?.set_len(valid)?;
+        }
+        let (segment, size) = match segments.back() {
+            Some((first_seq, path)) => {
+                // the last segment may be empty, e.g. just rotated.
+                history.next_seq = history.next_seq.max(first_seq.saturating_sub(1));
+                let segment = OpenOptions::new().append(true).open(path)?;
+                let size = segment.metadata()?.len();
+                (segment, size)
+            }
+            None => {
+                let first_seq = history.next_seq + 1;
+                let (segment, path) = create_segment(dir, first_seq)?;
+                segments.push_back((first_seq, path));
+                (segment, 0)
+            }
+        };
+        let journal = Self {
+            dir: dir.to_path_buf(),
+            segments,
+            segment,
+            size,
+            segment_size,
+        };
+        Ok((journal, history))
+    }
+
+    /// Appends `message` and syncs it, rotating the segment if full.
+    pub(crate) fn append(&mut self, message: &Message) -> io::Result<()> {
+        if self.size >= self.segment_size {
+            let (segment, path) = create_segment(&self.dir, message.seq)?;
+            self.segment = segment;
+            self.size = 0;
+            self.segments.push_back((message.seq, path));
+            prune(&mut self.segments)?;
+        }
+        let payload = serde_json::to_vec(message)?;
+        let len = u32::try_from(payload.len())
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "message too large"))?;
+        let mut record = Vec::with_capacity(HEADER_LEN + payload.len());
+        record.extend(len.to_le_bytes());
+        record.extend(crc32(&payload).to_le_bytes());
+        record.extend(payload);
+        let result = self
+            .segment
+            .write_all(&record)
+            .and_then(|_| self.segment.sync_data());
+        if let Err(e) = result {
+            // drops the partial record, not to bury the later ones.
+            let _ = self.segment.set_len(self.size);
+            return Err(e);
+        }
+        self.size += record.len() as u64;
+        Ok(())
+    }
+}
+
+const GROUP_PREFIX: &str = "group-";
+
+/// Returns the log directory of the group, hex-encoded not to escape
+/// `data_dir`.
+pub(crate) fn group_dir(data_dir: &Path, group_name: &str) -> PathBuf {
+    let hex: String = group_name.bytes().map(|b| format!("{b:02x}")).collect();
+    data_dir.join(format!("{GROUP_PREFIX}{hex}"))
+}
+
+/// Returns the names of the groups logged in `data_dir`, creating it if
+/// missing.
+pub(crate) fn group_names(data_dir: &Path) -> io::Result<Vec<String>> {
+    fs::create_dir_all(data_dir)?;
+    let mut names = vec![];
+    for entry in fs::read_dir(data_dir)? {
+        let entry = entry?;
+        if !entry.file_type()?.is_dir() {
+            continue;
+        }
+        let file_name = entry.file_name();
+        let hex = file_name
+            .to_str()
+            .and_then(|s| s.strip_prefix(GROUP_PREFIX));
+        let name = hex.and_then(|hex| {
+            let bytes = (0..hex.len())
+                .step_by(2)
+                .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+                .collect::<Option<Vec<_>>>()?;
+            String::from_utf8(bytes).ok()
+        });
+        if let Some(name) = name {
+            names.push(name);
+        }
+    }
+    Ok(names)
+}
+
+// returns the segments with the first sequence number, sorted by it.
+fn segments(dir: &Path) -> io::Result<VecDeque<(u64, PathBuf)>> {
+    let mut segments = vec![];
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let seq = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .and_then(|name| name.strip_suffix(".log"))
+            .and_then(|seq| seq.parse::<u64>().ok());
+        if let Some(seq) = seq {
+            segments.push((seq, path));
+        }
+    }
+    segments.sort();
+    Ok(segments.into())
+}
+
+// deletes the oldest segments, as long as the later ones hold the last
+// HISTORY_LEN messages.  The last segment starts at or before the next
+// message.
+fn prune(segments: &mut VecDeque<(u64, PathBuf)>) -> io::Result<()> {
+    let Some(&(last, _)) = segments.back() else {
+        return Ok(());
+    };
+    while let Some(&(next, _)) = segments.get(1) {
+        if next + HISTORY_LEN as u64 > last {
+            break;
+        }
+        let (_, path) = segments.pop_front().unwrap();
+        fs::remove_file(path)?;
+    }
+    Ok(())
+}
+
+fn create_segment(dir: &Path, first_seq: u64) -> io::Result<(File, PathBuf)> {
+    let path = dir.join(format!("{first_seq:020}.log"));
+    let segment = OpenOptions::new().create(true).append(true).open(&path)?;
+    sync_dir(dir)?;
+    Ok((segment, path))
+}
+
+// makes the new segment survive the crash.
+#[cfg(unix)]
+fn sync_dir(dir: &Path) -> io::Result<()> {
+    File::open(dir)?.sync_all()
+}
+
+#[cfg(not(unix))]
+fn sync_dir(_dir: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+// pushes the valid records to `history`, and returns their length in
+// bytes.
+fn replay(path: &Path, history: &mut History) -> io::Result<u64> {
+    let buf = fs::read(path)?;
+    let mut pos = 0;
+    while let Some(header) = buf.get(pos..pos + HEADER_LEN) {
+        let len = u32::from_le_bytes(header[..4].try_into().unwrap()) as usize;
+        let crc = u32::from_le_bytes(header[4..].try_into().unwrap());
+        let start = pos + HEADER_LEN;
+        let Some(payload) = buf.get(start..start + len) else {
+            break;
+        };
+        if crc32(payload) != crc {
+            break;
+        }
+        let Ok(message) = serde_json::from_slice::<Message>(payload) else {
+            break;
+        };
+        history.push(message);
+        pos = start + len;
+    }
+    Ok(pos as u64)
+}
+
+// CRC-32 (IEEE), bit by bit.
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &b in bytes {
+        crc ^= u32::from(b);
+        for _ in 0..8 {
+            crc = (crc >> 1) ^ (0xedb8_8320 & (crc & 1).wrapping_neg());
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{crc32, group_dir, group_names, segments, Journal};
+    use crate::{Message, HISTORY_LEN};
+    use std::fs::{self, OpenOptions};
+    use std::io::Write;
+    use std::sync::Arc;
+
+    fn message(seq: u64) -> Message {
+        Message {
+            seq,
+            sender: Arc::new("alice".to_string()),
+            message: Arc::new(format!("hello {seq}")),
+        }
+    }
+
+    fn seqs(journal: (Journal, crate::History)) -> Vec<u64> {
+        journal.1.messages.iter().map(|m| m.seq).collect()
+    }
+
+    #[test]
+    fn crc() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+    }
+
+    #[test]
+    fn append_and_replay() {
+        let dir = tempfile::tempdir().unwrap();
+        let (mut journal, history) = Journal::open(dir.path(), 1 << 20).unwrap();
+        assert!(history.messages.is_empty());
+        for seq in 1..=3 {
+            journal.append(&message(seq)).unwrap();
+        }
+        drop(journal);
+        let (mut journal, history) = Journal::open(dir.path(), 1 << 20).unwrap();
+        assert_eq!(history.next_seq, 3);
+        assert_eq!(*history.messages[2].message, "hello 3");
+        journal.append(&message(4)).unwrap();
+        drop(journal);
+        assert_eq!(
+            seqs(Journal::open(dir.path(), 1 << 20).unwrap()),
+            [1, 2, 3, 4]
+        );
+    }
+
+    #[test]
+    fn rotate_segments() {
+        let dir = tempfile::tempdir().unwrap();
+        let (mut journal, _) = Journal::open(dir.path(), 100).unwrap();
+        for seq in 1..=10 {
+            journal.append(&message(seq)).unwrap();
+        }
+        drop(journal);
+        let segments = segments(dir.path()).unwrap();
+        assert!(segments.len() > 2, "{segments:?}");
+        assert!(segments[0].1.ends_with("00000000000000000001.log"));
+        assert_eq!(
+            seqs(Journal::open(dir.path(), 100).unwrap()),
+            (1..=10).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn prune_segments() {
+        let dir = tempfile::tempdir().unwrap();
+        let (mut journal, _) = Journal::open(dir.path(), 100).unwrap();
+        let last = 3 * HISTORY_LEN as u64;
+        for seq in 1..=last {
+            journal.append(&message(seq)).unwrap();
+        }
+        drop(journal);
+        let segments = segments(dir.path()).unwrap();
+        // a segment more than the history at most.
+        let (first, _) = segments[1];
+        assert!(first + HISTORY_LEN as u64 > last, "{segments:?}");
+        let (_, history) = Journal::open(dir.path(), 100).unwrap();
+        assert_eq!(history.next_seq, last);
+        assert_eq!(history.messages.len(), HISTORY_LEN);
+        assert_eq!(history.messages[0].seq, last - HISTORY_LEN as u64 + 1);
+    }
+
+    #[test]
+    fn empty_last_segment() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("00000000000000000042.log"), b"").unwrap();
+        let (mut journal, history) = Journal::open(dir.path(), 1 << 20).unwrap();
+        assert_eq!(history.next_seq, 41);
+        journal.append(&message(42)).unwrap();
+        drop(journal);
+        assert_eq!(seqs(Journal::open(dir.path(), 1 << 20).unwrap()), [42]);
+    }
+
+    #[test]
+    fn truncate_torn_tail() {
+        let dir = tempfile::tempdir().unwrap();
+        let (mut journal, _) = Journal::open(dir.path(), 1 << 20).unwrap();
+        for seq in 1..=3 {
+            journal.append(&message(seq)).unwrap();
+        }
+        drop(journal);
+        let path = &segments(dir.path()).unwrap()[0].1;
+        let len = fs::metadata(path).unwrap().len();
+
+        // the partial record.
+        let f = OpenOptions::new().write(true).open(path).unwrap();
+        f.set_len(len - 3).unwrap();
+        let (mut journal, history) = Journal::open(dir.path(), 1 << 20).unwrap();
+        assert_eq!(history.next_seq, 2);
+        journal.append(&message(3)).unwrap();
+        drop(journal);
+        assert_eq!(fs::metadata(path).unwrap().len(), len);
+
+        // the garbage after the last record.
+        let mut f = OpenOptions::new().append(true).open(path).unwrap();
+        f.write_all(&[42; 20]).unwrap();
+        assert_eq!(seqs(Journal::open(dir.path(), 1 << 20).unwrap()), [1, 2, 3]);
+        assert_eq!(fs::metadata(path).unwrap().len(), len);
+    }
+
+    #[test]
+    fn checksum_mismatch() {
+        let dir = tempfile::tempdir().unwrap();
+        let (mut journal, _) = Journal::open(dir.path(), 1 << 20).unwrap();
+        for seq in 1..=3 {
+            journal.append(&message(seq)).unwrap();
+        }
+        drop(journal);
+        let path = &segments(dir.path()).unwrap()[0].1;
+        let mut buf = fs::read(path).unwrap();
+        let last = buf.len() - 2;
+        buf[last] ^= 1;
+        fs::write(path, buf).unwrap();
+        assert_eq!(seqs(Journal::open(dir.path(), 1 << 20).unwrap()), [1, 2]);
+    }
+
+    #[test]
+    fn corrupt_segment() {
+        let dir = tempfile::tempdir().unwrap();
+        let (mut journal, _) = Journal::open(dir.path(), 100).unwrap();
+        for seq in 1..=10 {
+            journal.append(&message(seq)).unwrap();
+        }
+        drop(journal);
+        // only the last segment may be torn.
+        let path = &segments(dir.path()).unwrap()[0].1;
+        let len = fs::metadata(path).unwrap().len();
+        let f = OpenOptions::new().write(true).open(path).unwrap();
+        f.set_len(len - 1).unwrap();
+        assert!(Journal::open(dir.path(), 100).is_err());
+    }
+
+    #[test]
+    fn group_dirs() {
+        let dir = tempfile::tempdir().unwrap();
+        let names = ["world", "../etc", "日本", ""];
+        for name in names {
+            let group_dir = group_dir(dir.path(), name);
+            assert_eq!(group_dir.parent(), Some(dir.path()));
+            fs::create_dir_all(group_dir).unwrap();
+        }
+        fs::create_dir(dir.path().join("group-not hex")).unwrap();
+        fs::create_dir(dir.path().join("abcd")).unwrap();
+        let mut found = group_names(dir.path()).unwrap();
+        found.sort();
+        let mut names = names.to_vec();
+        names.sort();
+        assert_eq!(found, names);
+    }
+}
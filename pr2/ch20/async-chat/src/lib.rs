@@ -1,26 +1,86 @@
 use eyre::eyre;
-use futures_lite::io::{self, AsyncBufReadExt, AsyncWriteExt};
+use futures_lite::io::{self, AsyncRead, AsyncWrite, AsyncWriteExt};
 use futures_lite::stream::{Stream, StreamExt};
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::path::PathBuf;
 use std::sync::Arc;
 use tracing::instrument;
 
-use async_broadcast::{broadcast, Receiver, RecvError, Sender};
+use async_broadcast::{broadcast, InactiveReceiver, Receiver, RecvError, Sender, TrySendError};
 use async_net::{AsyncToSocketAddrs, TcpListener, TcpStream};
-use async_std::task;
+use async_std::task::{self, JoinHandle};
+
+use journal::Journal;
+
+pub use codec::{
+    handshake, recv_packets, send_packet, AnyCodec, Codec, JsonLines, LengthPrefixed,
+    DEFAULT_MAX_FRAME_LEN,
+};
+
+mod codec;
+mod journal;
+
+/// Maximum length of the user name, in chars.
+pub const MAX_NAME_LEN: usize = 32;
+
+/// Number of the messages kept by each group.
+pub const HISTORY_LEN: usize = 100;
+
+/// Number of the recent messages replayed on join.
+pub const REPLAY_LEN: usize = 10;
+
+/// Default size of the log segment, in bytes.
+pub const DEFAULT_SEGMENT_SIZE: u64 = 1 << 20;
+
+/// Server configuration.
+#[derive(Clone, Debug)]
+pub struct Config {
+    /// Directory of the group message logs, or `None` to keep the
+    /// history only in memory.
+    pub data_dir: Option<PathBuf>,
+    /// Size of the log segment to rotate at, in bytes.
+    pub segment_size: u64,
+    /// Maximum frame length of either codec, in bytes.
+    pub max_frame_len: usize,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            data_dir: None,
+            segment_size: DEFAULT_SEGMENT_SIZE,
+            max_frame_len: DEFAULT_MAX_FRAME_LEN,
+        }
+    }
+}
 
 #[instrument(skip(addr))]
-pub async fn server(addr: impl AsyncToSocketAddrs) -> io::Result<()> {
-    let group_table = Arc::new(GroupTable::new());
+pub async fn server(addr: impl AsyncToSocketAddrs, config: Config) -> io::Result<()> {
     let listener = TcpListener::bind(addr).await?;
+    serve(listener, config).await
+}
+
+/// Serves the chat on the bound `listener`, with the groups replayed
+/// from `config.data_dir`.
+#[instrument(skip(listener))]
+pub async fn serve(listener: TcpListener, config: Config) -> io::Result<()> {
+    let max_frame_len = config.max_frame_len;
+    let group_table = Arc::new(GroupTable::open(config)?);
+    let user_table = Arc::new(UserTable::new());
     let mut incoming = listener.incoming();
 
     while let Some(mut socket) = incoming.try_next().await? {
         let peer = socket.peer_addr()?;
         let groups = group_table.clone();
+        let users = user_table.clone();
         task::spawn(async move {
-            if let Err(e) = reader(&mut socket, groups).await {
+            let result = match codec::accept(&mut socket, max_frame_len).await {
+                Ok(codec) => reader(&mut socket, codec, groups, users).await,
+                Err(e) => Err(e),
+            };
+            if let Err(e) = result {
                 eprintln!("{}: {e}", peer);
             } else {
                 println!("{}: done", peer);
@@ -31,113 +91,558 @@ pub async fn server(addr: impl AsyncToSocketAddrs) -> io::Result<()> {
 }
 
 #[instrument(skip(s))]
-async fn reader(s: &mut TcpStream, groups: Arc<GroupTable>) -> eyre::Result<()> {
-    let tx = Arc::new(Outbound::new(s.clone()));
-    let rx = io::BufReader::new(s);
-    let mut stream = recv_as_json(rx);
-
-    while let Some(result) = stream.next().await {
-        let req = match result {
-            Err(e) => return tx.send_err(e).await,
-            Ok(req) => req,
+async fn reader(
+    s: &mut TcpStream,
+    codec: AnyCodec,
+    groups: Arc<GroupTable>,
+    users: Arc<UserTable>,
+) -> eyre::Result<()> {
+    let tx = Arc::new(Outbound::new(s.clone(), codec));
+    let mut stream = recv_packets(codec, s);
+    let mut session = Session::new(tx.clone(), groups, users);
+
+    let result = async {
+        while let Some(result) = stream.next().await {
+            let req = match result {
+                Err(e) => return tx.send_err(e).await,
+                Ok(req) => req,
+            };
+            session.handle(req).await?;
+        }
+        Ok(())
+    }
+    .await;
+    // leaves the groups, also on error.
+    session.close().await;
+    result
+}
+
+/// State of the connection.
+#[derive(Debug)]
+struct Session {
+    tx: Arc<Outbound>,
+    groups: Arc<GroupTable>,
+    users: Arc<UserTable>,
+    // released on disconnect.
+    identity: Option<Identity>,
+    joined: HashSet<Arc<String>>,
+}
+
+impl Session {
+    fn new(tx: Arc<Outbound>, groups: Arc<GroupTable>, users: Arc<UserTable>) -> Self {
+        Self {
+            tx,
+            groups,
+            users,
+            identity: None,
+            joined: HashSet::new(),
+        }
+    }
+
+    async fn handle(&mut self, req: Request) -> eyre::Result<()> {
+        let name = match (&req, &self.identity) {
+            (Request::Login { name }, _) => return self.login(name.clone()).await,
+            (_, None) => {
+                let e = eyre!("login first, with /nick <name>");
+                return self.tx.send_err(e).await;
+            }
+            (_, Some(identity)) => identity.name.clone(),
         };
         match req {
-            Request::Join { group_name } => groups.get_or_create(group_name).join(tx.clone()),
+            Request::Login { .. } => unreachable!("handled above"),
+            Request::Join { group_name } => {
+                if !self.joined.insert(group_name.clone()) {
+                    let e = eyre!("already joined: {group_name:?}");
+                    return self.tx.send_err(e).await;
+                }
+                let result = self
+                    .groups
+                    .join(group_name.clone(), name, self.tx.clone())
+                    .await;
+                if let Err(e) = result {
+                    self.joined.remove(&group_name);
+                    self.tx.send_err(e.into()).await?;
+                }
+            }
+            Request::Leave { group_name } => {
+                if !self.joined.remove(&group_name) {
+                    let e = eyre!("not joined: {group_name:?}");
+                    return self.tx.send_err(e).await;
+                }
+                self.groups.leave(&group_name, &name).await;
+            }
+            Request::ListGroups => {
+                let group_names = self.groups.names();
+                self.tx.send(Response::Groups { group_names }).await?;
+            }
+            Request::ListMembers { group_name } => match self.groups.get(&group_name) {
+                Some(group) => {
+                    let names = group.members();
+                    self.tx
+                        .send(Response::Members { group_name, names })
+                        .await?;
+                }
+                None => {
+                    let e = eyre!("wrong group: {group_name:?}");
+                    self.tx.send_err(e).await?;
+                }
+            },
+            Request::Whisper { to, message } => match self.users.get(&to) {
+                Some(outbound) => {
+                    let resp = Response::Private {
+                        sender: name,
+                        message,
+                    };
+                    // the recipient may be gone meanwhile.
+                    if outbound.send(resp).await.is_err() {
+                        self.tx.send_err(eyre!("user offline: {to:?}")).await?;
+                    }
+                }
+                None => self.tx.send_err(eyre!("user offline: {to:?}")).await?,
+            },
+            Request::History { group_name, since } => match self.groups.get(&group_name) {
+                Some(group) => {
+                    let (skipped, messages) = group.history(since);
+                    if skipped > 0 {
+                        let group_name = group_name.clone();
+                        self.tx
+                            .send(Response::Lagged {
+                                group_name,
+                                skipped,
+                            })
+                            .await?;
+                    }
+                    for message in messages {
+                        self.tx.send(message.to_response(&group_name)).await?;
+                    }
+                }
+                None => {
+                    let e = eyre!("wrong group: {group_name:?}");
+                    self.tx.send_err(e).await?;
+                }
+            },
             Request::Post {
                 group_name,
                 message,
-            } => match groups.get(&group_name) {
+            } => match self.groups.get(&group_name) {
                 Some(group) => {
-                    if let Err(e) = group.post(message).await {
-                        tx.send_err(e).await?;
+                    // the members get it in the larger frame.
+                    let resp = Response::Message {
+                        group_name,
+                        seq: u64::MAX,
+                        sender: name.clone(),
+                        message: message.clone(),
+                    };
+                    let max_frame_len = self.groups.config.max_frame_len;
+                    if let Err(e) = codec::check_frame_len(&resp, max_frame_len) {
+                        return self.tx.send_err(eyre!("message too large: {e}")).await;
+                    }
+                    if let Err(e) = group.post(name, message).await {
+                        self.tx.send_err(e).await?;
                     }
                 }
                 None => {
                     let e = eyre!("wrong group: {group_name:?}");
-                    tx.send_err(e).await?;
+                    self.tx.send_err(e).await?;
                 }
             },
         }
+        Ok(())
+    }
+
+    async fn login(&mut self, name: Arc<String>) -> eyre::Result<()> {
+        let old = self.identity.as_ref().map(|identity| identity.name.clone());
+        if old.as_ref() != Some(&name) {
+            match Identity::new(name.clone(), self.tx.clone(), self.users.clone()) {
+                // releases the old name, if any.
+                Ok(identity) => self.identity = Some(identity),
+                Err(e) => return self.tx.send_err(e).await,
+            }
+        }
+        if let Some(old) = old {
+            for group_name in &self.joined {
+                if let Some(group) = self.groups.get(group_name) {
+                    group.rename(&old, name.clone());
+                }
+            }
+        }
+        self.tx.send(Response::Welcome { name }).await
+    }
+
+    async fn close(&mut self) {
+        if let Some(identity) = &self.identity {
+            for group_name in self.joined.drain() {
+                self.groups.leave(&group_name, &identity.name).await;
+            }
+        }
     }
-    Ok(())
 }
 
-#[instrument(skip(tx, rx))]
+#[instrument(skip(tx, replay, rx))]
 async fn writer(
     name: Arc<String>,
     tx: Arc<Outbound>,
-    mut rx: Receiver<Arc<String>>,
+    replay: Vec<Message>,
+    mut rx: Receiver<Message>,
 ) -> eyre::Result<()> {
+    for message in replay {
+        tx.send(message.to_response(&name)).await?;
+    }
     loop {
         let packet = match rx.recv().await {
-            Ok(message) => Response::Message {
+            Ok(message) => message.to_response(&name),
+            Err(RecvError::Overflowed(skipped)) => Response::Lagged {
                 group_name: name.clone(),
-                message,
+                skipped,
             },
             Err(RecvError::Closed) => return Err(eyre!("group closed")),
-            Err(e) => Response::Error(e.to_string()),
         };
         tx.send(packet).await?;
     }
 }
 
 #[derive(Debug)]
-struct GroupTable(std::sync::Mutex<HashMap<Arc<String>, Arc<Group>>>);
+struct GroupTable {
+    groups: std::sync::Mutex<HashMap<Arc<String>, Arc<Group>>>,
+    // serializes opening the logged groups, out of the `groups` lock.
+    opening: async_lock::Mutex<()>,
+    config: Config,
+}
 
 impl GroupTable {
+    // with the groups logged in the data directory.
+    fn open(config: Config) -> io::Result<Self> {
+        let mut groups = HashMap::new();
+        if let Some(data_dir) = &config.data_dir {
+            for name in journal::group_names(data_dir)? {
+                let name = Arc::new(name);
+                let group = Group::open(name.clone(), &config)?;
+                groups.insert(name, Arc::new(group));
+            }
+        }
+        Ok(Self {
+            groups: std::sync::Mutex::new(groups),
+            opening: async_lock::Mutex::new(()),
+            config,
+        })
+    }
+
+    fn get(&self, name: &String) -> Option<Arc<Group>> {
+        self.groups.lock().unwrap().get(name).cloned()
+    }
+
+    /// Returns the group names, sorted.
+    fn names(&self) -> Vec<Arc<String>> {
+        let mut names: Vec<_> = self.groups.lock().unwrap().keys().cloned().collect();
+        names.sort();
+        names
+    }
+
+    // creates the group, if not yet.  The group in memory is created
+    // under the lock not to race with leave(), and the logged one is
+    // replayed out of it, as it's never removed.
+    async fn join(
+        &self,
+        name: Arc<String>,
+        member: Arc<String>,
+        tx: Arc<Outbound>,
+    ) -> io::Result<()> {
+        if self.config.data_dir.is_none() {
+            let mut groups = self.groups.lock().unwrap();
+            let group = groups
+                .entry(name.clone())
+                .or_insert_with(|| Arc::new(Group::new(name, None, History::default())));
+            group.join(member, tx);
+            return Ok(());
+        }
+        let group = match self.get(&name) {
+            Some(group) => group,
+            None => {
+                // one Journal per directory.
+                let _opening = self.opening.lock().await;
+                match self.get(&name) {
+                    Some(group) => group,
+                    None => {
+                        let config = self.config.clone();
+                        let group =
+                            task::spawn_blocking(move || Group::open(name, &config)).await?;
+                        let group = Arc::new(group);
+                        let mut groups = self.groups.lock().unwrap();
+                        groups.insert(group.name.clone(), group.clone());
+                        group
+                    }
+                }
+            }
+        };
+        group.join(member, tx);
+        Ok(())
+    }
+
+    // removes the group in memory when the last member leaves.  The
+    // logged one is kept, to keep appending to the same log.
+    async fn leave(&self, name: &String, member: &String) {
+        let writer = {
+            let mut table = self.groups.lock().unwrap();
+            let Some(group) = table.get(name) else {
+                return;
+            };
+            let writer = group.leave(member);
+            if group.journal.is_none() && group.members.lock().unwrap().is_empty() {
+                table.remove(name);
+            }
+            writer
+        };
+        if let Some(writer) = writer {
+            writer.cancel().await;
+        }
+    }
+}
+
+/// Logged in users, by the name unique across the server.
+#[derive(Debug)]
+struct UserTable(std::sync::Mutex<HashMap<Arc<String>, Arc<Outbound>>>);
+
+impl UserTable {
     fn new() -> Self {
         Self(std::sync::Mutex::new(HashMap::new()))
     }
 
-    fn get(&self, name: &String) -> Option<Arc<Group>> {
+    fn get(&self, name: &String) -> Option<Arc<Outbound>> {
         self.0.lock().unwrap().get(name).cloned()
     }
 
-    fn get_or_create(&self, name: Arc<String>) -> Arc<Group> {
-        self.0
-            .lock()
-            .unwrap()
-            .entry(name.clone())
-            .or_insert_with(|| Arc::new(Group::new(name)))
-            .clone()
+    // returns false if the name is taken.
+    fn claim(&self, name: Arc<String>, tx: Arc<Outbound>) -> bool {
+        match self.0.lock().unwrap().entry(name) {
+            Entry::Occupied(_) => false,
+            Entry::Vacant(entry) => {
+                entry.insert(tx);
+                true
+            }
+        }
+    }
+
+    fn release(&self, name: &String) {
+        self.0.lock().unwrap().remove(name);
+    }
+}
+
+/// Name of the connection, released on drop.
+#[derive(Debug)]
+struct Identity {
+    name: Arc<String>,
+    users: Arc<UserTable>,
+}
+
+impl Identity {
+    fn new(name: Arc<String>, tx: Arc<Outbound>, users: Arc<UserTable>) -> eyre::Result<Self> {
+        if name.is_empty() || name.chars().count() > MAX_NAME_LEN {
+            return Err(eyre!("name should be 1 to {MAX_NAME_LEN} chars: {name:?}"));
+        }
+        if name.chars().any(|c| c.is_whitespace() || c.is_control()) {
+            return Err(eyre!("name should not contain spaces: {name:?}"));
+        }
+        if !users.claim(name.clone(), tx) {
+            return Err(eyre!("name already taken: {name:?}"));
+        }
+        Ok(Self { name, users })
+    }
+}
+
+impl Drop for Identity {
+    fn drop(&mut self) {
+        self.users.release(&self.name);
+    }
+}
+
+/// Message posted to the group.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+struct Message {
+    seq: u64,
+    sender: Arc<String>,
+    message: Arc<String>,
+}
+
+impl Message {
+    fn to_response(&self, group_name: &Arc<String>) -> Response {
+        Response::Message {
+            group_name: group_name.clone(),
+            seq: self.seq,
+            sender: self.sender.clone(),
+            message: self.message.clone(),
+        }
+    }
+}
+
+/// Recent messages of the group, up to [`HISTORY_LEN`].
+#[derive(Debug, Default)]
+struct History {
+    messages: VecDeque<Message>,
+    // sequence number of the next message, from 1.
+    next_seq: u64,
+}
+
+impl History {
+    // returns the message to post next.
+    fn next(&self, sender: Arc<String>, message: Arc<String>) -> Message {
+        Message {
+            seq: self.next_seq + 1,
+            sender,
+            message,
+        }
+    }
+
+    fn push(&mut self, message: Message) {
+        self.next_seq = message.seq;
+        if self.messages.len() == HISTORY_LEN {
+            self.messages.pop_front();
+        }
+        self.messages.push_back(message);
+    }
+
+    // returns the number of the messages after `since` no longer kept,
+    // and the kept ones.
+    fn since(&self, since: u64) -> (u64, Vec<Message>) {
+        let first = self.messages.front().map_or(self.next_seq + 1, |m| m.seq);
+        let skipped = first.saturating_sub(since.saturating_add(1));
+        let messages = self
+            .messages
+            .iter()
+            .filter(|m| m.seq > since)
+            .cloned()
+            .collect();
+        (skipped, messages)
+    }
+
+    fn recent(&self, n: usize) -> Vec<Message> {
+        let start = self.messages.len().saturating_sub(n);
+        self.messages.range(start..).cloned().collect()
     }
 }
 
 #[derive(Debug)]
 struct Group {
     name: Arc<String>,
-    tx: Sender<Arc<String>>,
-    _rx: Receiver<Arc<String>>,
+    tx: Sender<Message>,
+    // keeps the channel open without the members.
+    _rx: InactiveReceiver<Message>,
+    history: std::sync::Mutex<History>,
+    // orders the posts, by the sequence number, and held across the
+    // journal append.
+    posting: async_lock::Mutex<()>,
+    // appended by the blocking task, not to block the executor.
+    journal: Option<Arc<std::sync::Mutex<Journal>>>,
+    // writer task of each member, by the name.
+    members: std::sync::Mutex<HashMap<Arc<String>, JoinHandle<eyre::Result<()>>>>,
 }
 
 impl Group {
-    fn new(name: Arc<String>) -> Self {
-        let (tx, _rx) = broadcast(8);
-        Self { name, tx, _rx }
+    // with the history replayed from the log, if any.
+    fn open(name: Arc<String>, config: &Config) -> io::Result<Self> {
+        match &config.data_dir {
+            Some(data_dir) => {
+                let dir = journal::group_dir(data_dir, &name);
+                let (journal, history) = Journal::open(&dir, config.segment_size)?;
+                Ok(Self::new(name, Some(journal), history))
+            }
+            None => Ok(Self::new(name, None, History::default())),
+        }
+    }
+
+    fn new(name: Arc<String>, journal: Option<Journal>, history: History) -> Self {
+        let (mut tx, rx) = broadcast(8);
+        // drops the oldest message for the slow members, who get lagged.
+        tx.set_overflow(true);
+        let members = std::sync::Mutex::new(HashMap::new());
+        Self {
+            name,
+            tx,
+            _rx: rx.deactivate(),
+            history: std::sync::Mutex::new(history),
+            posting: async_lock::Mutex::new(()),
+            journal: journal.map(|journal| Arc::new(std::sync::Mutex::new(journal))),
+            members,
+        }
     }
 
-    fn join(&self, tx: Arc<Outbound>) {
-        let rx = self.tx.new_receiver();
-        task::spawn(writer(self.name.clone(), tx, rx));
+    fn join(&self, member: Arc<String>, tx: Arc<Outbound>) {
+        // under the lock, not to miss or repeat the message posted
+        // meanwhile.
+        let (replay, rx) = {
+            let history = self.history.lock().unwrap();
+            (history.recent(REPLAY_LEN), self.tx.new_receiver())
+        };
+        let writer = task::spawn(writer(self.name.clone(), tx, replay, rx));
+        self.members.lock().unwrap().insert(member, writer);
     }
 
-    async fn post(&self, msg: Arc<String>) -> eyre::Result<()> {
-        self.tx.broadcast(msg).await?;
-        Ok(())
+    // returns the writer task to cancel.
+    fn leave(&self, member: &String) -> Option<JoinHandle<eyre::Result<()>>> {
+        self.members.lock().unwrap().remove(member)
+    }
+
+    fn rename(&self, old: &String, new: Arc<String>) {
+        let mut members = self.members.lock().unwrap();
+        if let Some(writer) = members.remove(old) {
+            members.insert(new, writer);
+        }
+    }
+
+    /// Returns the member names, sorted.
+    fn members(&self) -> Vec<Arc<String>> {
+        let mut names: Vec<_> = self.members.lock().unwrap().keys().cloned().collect();
+        names.sort();
+        names
+    }
+
+    async fn post(&self, sender: Arc<String>, message: Arc<String>) -> eyre::Result<()> {
+        let _posting = self.posting.lock().await;
+        let message = self.history.lock().unwrap().next(sender, message);
+        // logged first, not to lose the message seen.
+        if let Some(journal) = &self.journal {
+            let journal = journal.clone();
+            let logged = message.clone();
+            task::spawn_blocking(move || journal.lock().unwrap().append(&logged)).await?;
+        }
+        self.history.lock().unwrap().push(message.clone());
+        // never full with the overflow, nor inactive without the members.
+        match self.tx.try_broadcast(message) {
+            Ok(_) | Err(TrySendError::Inactive(_)) => Ok(()),
+            Err(e) => Err(eyre!("{e}")),
+        }
+    }
+
+    /// Returns the messages after `since`, with the number of the
+    /// skipped ones no longer kept.
+    fn history(&self, since: u64) -> (u64, Vec<Message>) {
+        self.history.lock().unwrap().since(since)
     }
 }
 
 #[derive(Debug)]
-struct Outbound(async_lock::Mutex<TcpStream>);
+struct Outbound {
+    stream: async_lock::Mutex<TcpStream>,
+    codec: AnyCodec,
+}
 
 impl Outbound {
-    fn new(s: TcpStream) -> Self {
-        Self(async_lock::Mutex::new(s))
+    fn new(s: TcpStream, codec: AnyCodec) -> Self {
+        Self {
+            stream: async_lock::Mutex::new(s),
+            codec,
+        }
     }
 
+    // sends the error instead of the response over max_frame_len, not
+    // to lose the connection, e.g. the writer of the group.
     async fn send(&self, packet: Response) -> eyre::Result<()> {
-        let mut guard = self.0.lock().await;
-        send_as_json(&mut *guard, &packet).await?;
+        let mut buf = vec![];
+        if let Err(e) = self.codec.encode(&packet, &mut buf) {
+            let resp = Response::Error(format!("response dropped: {e}"));
+            self.codec.encode(&resp, &mut buf)?;
+        }
+        let mut guard = self.stream.lock().await;
+        guard.write_all(&buf).await?;
         guard.flush().await?;
         Ok(())
     }
@@ -148,67 +653,142 @@ impl Outbound {
     }
 }
 
+/// Sends `packet` as the line of JSON, i.e. [`JsonLines`].
 pub async fn send_as_json<S, P>(tx: &mut S, packet: &P) -> eyre::Result<()>
 where
-    S: AsyncWriteExt + Unpin,
+    S: AsyncWrite + Unpin,
     P: Serialize,
 {
-    let mut json = serde_json::to_string(packet)?;
-    json.push('\n');
-    tx.write_all(json.as_bytes()).await?;
-    Ok(())
+    send_packet(&JsonLines::default(), tx, packet).await
 }
 
-pub fn recv_as_json<S, P>(rx: S) -> impl Stream<Item = eyre::Result<P>>
+/// Returns the stream of the lines of JSON, i.e. [`JsonLines`].
+pub fn recv_as_json<S, P>(rx: S) -> impl Stream<Item = eyre::Result<P>> + Unpin
 where
-    S: AsyncBufReadExt + Unpin,
+    S: AsyncRead + Unpin,
     P: DeserializeOwned,
 {
-    rx.lines().map(|result| -> eyre::Result<P> {
-        let line = result?;
-        let parsed = serde_json::from_str::<P>(&line)?;
-        Ok(parsed)
-    })
+    recv_packets(JsonLines::default(), rx)
 }
 
 #[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
 pub enum Request {
+    /// Logs in, or renames, with the name unique across the server.
+    Login {
+        name: Arc<String>,
+    },
     Join {
         group_name: Arc<String>,
     },
+    Leave {
+        group_name: Arc<String>,
+    },
+    ListGroups,
+    ListMembers {
+        group_name: Arc<String>,
+    },
     Post {
         group_name: Arc<String>,
         message: Arc<String>,
     },
+    /// Messages of the group after the sequence number `since`.
+    History {
+        group_name: Arc<String>,
+        since: u64,
+    },
+    /// Private message to the user `to`.
+    Whisper {
+        to: Arc<String>,
+        message: Arc<String>,
+    },
 }
 
 #[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
 pub enum Response {
+    /// Logged in as `name`.
+    Welcome {
+        name: Arc<String>,
+    },
+    /// Message of the group, by the sequence number from 1.
     Message {
         group_name: Arc<String>,
+        seq: u64,
+        sender: Arc<String>,
         message: Arc<String>,
     },
+    /// `skipped` messages of the group were missed, for being too slow
+    /// or too old.
+    Lagged {
+        group_name: Arc<String>,
+        skipped: u64,
+    },
+    /// Private message from the user `sender`.
+    Private {
+        sender: Arc<String>,
+        message: Arc<String>,
+    },
+    /// Reply to [`Request::ListGroups`], sorted.
+    Groups {
+        group_names: Vec<Arc<String>>,
+    },
+    /// Reply to [`Request::ListMembers`], sorted.
+    Members {
+        group_name: Arc<String>,
+        names: Vec<Arc<String>>,
+    },
     Error(String),
 }
 
 impl TryFrom<String> for Request {
     type Error = eyre::Report;
 
-    // simple line parser.
     fn try_from(req: String) -> eyre::Result<Self> {
-        let req = req.trim_end();
+        Self::try_from(req.as_str())
+    }
+}
+
+impl TryFrom<&str> for Request {
+    type Error = eyre::Report;
+
+    // simple line parser, e.g. `/command args`, or the post as
+    // `group message` or `group: message`, split at the first space or
+    // colon.
+    fn try_from(req: &str) -> eyre::Result<Self> {
+        let req = req.trim();
         if req.starts_with('/') {
-            match req.split_once(' ') {
-                Some((command, rest)) => match command.to_lowercase().trim_end() {
-                    "/join" => Ok(Self::Join {
-                        group_name: Arc::new(rest.trim_start().to_string()),
+            let (command, rest) = req.split_once(' ').unwrap_or((req, ""));
+            let arg = || match rest.trim_start() {
+                "" => Err(eyre!("missing argument: {req:?}")),
+                arg => Ok(Arc::new(arg.to_string())),
+            };
+            match command.to_lowercase().as_str() {
+                "/join" => Ok(Self::Join { group_name: arg()? }),
+                "/leave" => Ok(Self::Leave { group_name: arg()? }),
+                "/list" => Ok(Self::ListGroups),
+                "/who" => Ok(Self::ListMembers { group_name: arg()? }),
+                "/history" => {
+                    let arg = arg()?;
+                    let (group_name, since) = match arg.split_once(' ') {
+                        Some((group_name, since)) => (group_name, since.trim().parse()?),
+                        None => (arg.as_str(), 0),
+                    };
+                    Ok(Self::History {
+                        group_name: Arc::new(group_name.to_string()),
+                        since,
+                    })
+                }
+                "/nick" => Ok(Self::Login { name: arg()? }),
+                "/msg" => match arg()?.split_once(' ') {
+                    Some((to, message)) => Ok(Self::Whisper {
+                        to: Arc::new(to.to_string()),
+                        message: Arc::new(message.trim().to_string()),
                     }),
-                    _ => Err(eyre!("unsupported command: {command:?}")),
+                    None => Err(eyre!("missing message: {req:?}")),
                 },
-                None => Err(eyre!("invalid request: {req:?}")),
+                _ => Err(eyre!("unsupported command: {command:?}")),
             }
         } else {
-            match req.split_once(' ') {
+            match req.split_once([' ', ':']) {
                 Some((group, message)) => Ok(Self::Post {
                     group_name: Arc::new(group.trim().to_string()),
                     message: Arc::new(message.trim().to_string()),
@@ -221,9 +801,60 @@ impl TryFrom<String> for Request {
 
 #[cfg(test)]
 mod tests {
-    use super::Request;
+    use super::{
+        handshake, recv_packets, send_as_json, send_packet, serve, AnyCodec, Config, Group,
+        LengthPrefixed, Request, Response, HISTORY_LEN, REPLAY_LEN,
+    };
+    use async_broadcast::RecvError;
+    use async_net::{TcpListener, TcpStream};
+    use async_std::task;
+    use futures_lite::io::{AsyncReadExt, AsyncWriteExt};
+    use futures_lite::stream::{Stream, StreamExt};
+    use std::pin::Pin;
     use std::sync::Arc;
 
+    type Responses = Pin<Box<dyn Stream<Item = eyre::Result<Response>> + Send>>;
+
+    fn arc(s: &str) -> Arc<String> {
+        Arc::new(s.to_string())
+    }
+
+    async fn start() -> String {
+        start_with(Config::default()).await
+    }
+
+    async fn start_with(config: Config) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        task::spawn(serve(listener, config));
+        addr
+    }
+
+    async fn connect(addr: &str) -> (TcpStream, Responses) {
+        connect_with(addr, AnyCodec::default()).await
+    }
+
+    async fn connect_with(addr: &str, codec: AnyCodec) -> (TcpStream, Responses) {
+        let mut s = TcpStream::connect(addr).await.unwrap();
+        handshake(&mut s, &codec).await.unwrap();
+        let responses = Box::pin(recv_packets(codec, s.clone()));
+        (s, responses)
+    }
+
+    async fn send(s: &mut TcpStream, line: &str) {
+        send_as_json(s, &Request::try_from(line).unwrap())
+            .await
+            .unwrap();
+    }
+
+    async fn next(responses: &mut Responses) -> Response {
+        responses.next().await.unwrap().unwrap()
+    }
+
+    fn is_error(resp: &Response) -> bool {
+        matches!(resp, Response::Error(_))
+    }
+
     #[test]
     fn request_join_try_from_ok() {
         let requests = ["/join world", "/join   world"];
@@ -237,7 +868,13 @@ mod tests {
 
     #[test]
     fn request_post_try_from_ok() {
-        let requests = ["world: hello", "world:   hello", "  world:   hello   "];
+        let requests = [
+            "world hello",
+            "world   hello",
+            "world: hello",
+            "world:   hello",
+            "  world:   hello   ",
+        ];
         let want = Request::Post {
             group_name: Arc::new("world".to_string()),
             message: Arc::new("hello".to_string()),
@@ -246,4 +883,607 @@ mod tests {
             assert_eq!(Request::try_from(request).unwrap(), want);
         }
     }
+
+    #[test]
+    fn request_nick_try_from_ok() {
+        let want = Request::Login { name: arc("alice") };
+        assert_eq!(Request::try_from("/nick alice").unwrap(), want);
+        assert_eq!(
+            Request::try_from("/NICK  alice ".to_string()).unwrap(),
+            want
+        );
+        assert!(Request::try_from("/nick").is_err());
+        assert!(Request::try_from("/part world").is_err());
+    }
+
+    #[test]
+    fn login_and_post() {
+        task::block_on(async {
+            let addr = start().await;
+            let (mut alice, mut alice_rx) = connect(&addr).await;
+            let (mut bob, mut bob_rx) = connect(&addr).await;
+
+            send(&mut alice, "/join world").await;
+            assert!(is_error(&next(&mut alice_rx).await));
+            send(&mut alice, "/nick alice").await;
+            assert_eq!(
+                next(&mut alice_rx).await,
+                Response::Welcome { name: arc("alice") }
+            );
+            send(&mut alice, "/join world").await;
+            send(&mut bob, "/nick bob").await;
+            next(&mut bob_rx).await;
+            send(&mut bob, "/join world").await;
+            // waits for the join, by the round trip.
+            send(&mut bob, "/list").await;
+            assert_eq!(
+                next(&mut bob_rx).await,
+                Response::Groups {
+                    group_names: vec![arc("world")]
+                }
+            );
+
+            send(&mut alice, "world: hello").await;
+            let want = Response::Message {
+                group_name: arc("world"),
+                seq: 1,
+                sender: arc("alice"),
+                message: arc("hello"),
+            };
+            assert_eq!(next(&mut alice_rx).await, want);
+            assert_eq!(next(&mut bob_rx).await, want);
+        })
+    }
+
+    #[test]
+    fn unique_names() {
+        task::block_on(async {
+            let addr = start().await;
+            let (mut alice, mut alice_rx) = connect(&addr).await;
+            let (mut other, mut other_rx) = connect(&addr).await;
+
+            send(&mut alice, "/nick alice").await;
+            next(&mut alice_rx).await;
+            send(&mut other, "/nick alice").await;
+            assert!(is_error(&next(&mut other_rx).await));
+            send_as_json(&mut other, &Request::Login { name: arc("a b") })
+                .await
+                .unwrap();
+            assert!(is_error(&next(&mut other_rx).await));
+
+            // renaming releases the old name.
+            send(&mut alice, "/nick alicia").await;
+            next(&mut alice_rx).await;
+            send(&mut other, "/nick alice").await;
+            assert_eq!(
+                next(&mut other_rx).await,
+                Response::Welcome { name: arc("alice") }
+            );
+
+            // so does the disconnect.
+            drop((alice, alice_rx));
+            let (mut third, mut third_rx) = connect(&addr).await;
+            let mut resp = Response::Error(String::new());
+            for _ in 0..100 {
+                send(&mut third, "/nick alicia").await;
+                resp = next(&mut third_rx).await;
+                if !is_error(&resp) {
+                    break;
+                }
+                task::sleep(std::time::Duration::from_millis(10)).await;
+            }
+            assert_eq!(
+                resp,
+                Response::Welcome {
+                    name: arc("alicia")
+                }
+            );
+        })
+    }
+
+    #[test]
+    fn request_commands_try_from_ok() {
+        let world = arc("world");
+        let requests = [
+            (
+                "/leave world",
+                Request::Leave {
+                    group_name: world.clone(),
+                },
+            ),
+            ("/list", Request::ListGroups),
+            ("/LIST ", Request::ListGroups),
+            ("/who  world", Request::ListMembers { group_name: world }),
+        ];
+        for (req, want) in requests {
+            assert_eq!(Request::try_from(req).unwrap(), want, "{req}");
+        }
+        assert!(Request::try_from("/leave").is_err());
+        assert!(Request::try_from("/who ").is_err());
+    }
+
+    #[test]
+    fn leave_and_list() {
+        task::block_on(async {
+            let addr = start().await;
+            let (mut alice, mut alice_rx) = connect(&addr).await;
+            let (mut bob, mut bob_rx) = connect(&addr).await;
+            send(&mut alice, "/nick alice").await;
+            next(&mut alice_rx).await;
+            send(&mut bob, "/nick bob").await;
+            next(&mut bob_rx).await;
+
+            for group in ["world", "rust"] {
+                send(&mut alice, &format!("/join {group}")).await;
+            }
+            send(&mut bob, "/join world").await;
+            send(&mut alice, "/join world").await;
+            assert!(is_error(&next(&mut alice_rx).await));
+            send(&mut bob, "/list").await;
+            assert_eq!(
+                next(&mut bob_rx).await,
+                Response::Groups {
+                    group_names: vec![arc("rust"), arc("world")]
+                }
+            );
+            send(&mut bob, "/who world").await;
+            assert_eq!(
+                next(&mut bob_rx).await,
+                Response::Members {
+                    group_name: arc("world"),
+                    names: vec![arc("alice"), arc("bob")]
+                }
+            );
+
+            // no more messages after leaving.
+            send(&mut alice, "/leave world").await;
+            send(&mut alice, "/leave world").await;
+            assert!(is_error(&next(&mut alice_rx).await));
+            send(&mut bob, "world: hello").await;
+            next(&mut bob_rx).await;
+            send(&mut alice, "/who world").await;
+            assert_eq!(
+                next(&mut alice_rx).await,
+                Response::Members {
+                    group_name: arc("world"),
+                    names: vec![arc("bob")]
+                }
+            );
+
+            // the empty group is removed.
+            send(&mut bob, "/leave world").await;
+            send(&mut bob, "/list").await;
+            assert_eq!(
+                next(&mut bob_rx).await,
+                Response::Groups {
+                    group_names: vec![arc("rust")]
+                }
+            );
+        })
+    }
+
+    #[test]
+    fn leave_on_disconnect() {
+        task::block_on(async {
+            let addr = start().await;
+            let (mut alice, mut alice_rx) = connect(&addr).await;
+            let (mut bob, mut bob_rx) = connect(&addr).await;
+            send(&mut alice, "/nick alice").await;
+            next(&mut alice_rx).await;
+            send(&mut alice, "/join world").await;
+            send(&mut alice, "/list").await;
+            next(&mut alice_rx).await;
+            send(&mut bob, "/nick bob").await;
+            next(&mut bob_rx).await;
+
+            drop((alice, alice_rx));
+            let mut resp = Response::Error(String::new());
+            for _ in 0..100 {
+                send(&mut bob, "/list").await;
+                resp = next(&mut bob_rx).await;
+                if resp
+                    == (Response::Groups {
+                        group_names: vec![],
+                    })
+                {
+                    break;
+                }
+                task::sleep(std::time::Duration::from_millis(10)).await;
+            }
+            assert_eq!(
+                resp,
+                Response::Groups {
+                    group_names: vec![]
+                }
+            );
+        })
+    }
+
+    #[test]
+    fn request_msg_try_from_ok() {
+        let want = Request::Whisper {
+            to: arc("bob"),
+            message: arc("hi there"),
+        };
+        assert_eq!(Request::try_from("/msg bob hi there").unwrap(), want);
+        assert_eq!(Request::try_from("/msg  bob   hi there ").unwrap(), want);
+        assert!(Request::try_from("/msg bob").is_err());
+        assert!(Request::try_from("/msg").is_err());
+    }
+
+    #[test]
+    fn whisper() {
+        task::block_on(async {
+            let addr = start().await;
+            let (mut alice, mut alice_rx) = connect(&addr).await;
+            let (mut bob, mut bob_rx) = connect(&addr).await;
+            send(&mut alice, "/nick alice").await;
+            next(&mut alice_rx).await;
+            send(&mut bob, "/nick bob").await;
+            next(&mut bob_rx).await;
+
+            send(&mut alice, "/msg bob hi bob").await;
+            assert_eq!(
+                next(&mut bob_rx).await,
+                Response::Private {
+                    sender: arc("alice"),
+                    message: arc("hi bob")
+                }
+            );
+            send(&mut alice, "/msg carol hi carol").await;
+            assert!(is_error(&next(&mut alice_rx).await));
+
+            // follows the rename.
+            send(&mut bob, "/nick robert").await;
+            next(&mut bob_rx).await;
+            send(&mut alice, "/msg bob hi bob").await;
+            assert!(is_error(&next(&mut alice_rx).await));
+            send(&mut alice, "/msg robert hi robert").await;
+            assert_eq!(
+                next(&mut bob_rx).await,
+                Response::Private {
+                    sender: arc("alice"),
+                    message: arc("hi robert")
+                }
+            );
+        })
+    }
+
+    #[test]
+    fn request_history_try_from_ok() {
+        let requests = [
+            ("/history world", 0),
+            ("/history world 42", 42),
+            ("/history  world  7 ", 7),
+        ];
+        for (req, since) in requests {
+            let want = Request::History {
+                group_name: arc("world"),
+                since,
+            };
+            assert_eq!(Request::try_from(req).unwrap(), want, "{req}");
+        }
+        assert!(Request::try_from("/history world x").is_err());
+        assert!(Request::try_from("/history").is_err());
+    }
+
+    fn message(group_name: &str, seq: u64, sender: &str, message: &str) -> Response {
+        Response::Message {
+            group_name: arc(group_name),
+            seq,
+            sender: arc(sender),
+            message: arc(message),
+        }
+    }
+
+    #[test]
+    fn history_ring_buffer() {
+        task::block_on(async {
+            let group = Group::open(arc("world"), &Config::default()).unwrap();
+            let alice = arc("alice");
+            for i in 0..HISTORY_LEN + 5 {
+                group
+                    .post(alice.clone(), arc(&i.to_string()))
+                    .await
+                    .unwrap();
+            }
+            let (skipped, messages) = group.history(0);
+            assert_eq!(skipped, 5);
+            assert_eq!(messages.len(), HISTORY_LEN);
+            assert_eq!(messages[0].seq, 6);
+            assert_eq!(*messages[0].message, "5");
+            let (skipped, messages) = group.history(100);
+            assert_eq!(skipped, 0);
+            let seqs: Vec<_> = messages.iter().map(|m| m.seq).collect();
+            assert_eq!(seqs, [101, 102, 103, 104, 105]);
+            assert!(group.history(105).1.is_empty());
+        })
+    }
+
+    #[test]
+    fn history_since_max() {
+        task::block_on(async {
+            let group = Group::open(arc("world"), &Config::default()).unwrap();
+            let (skipped, messages) = group.history(u64::MAX);
+            assert_eq!(skipped, 0);
+            assert!(messages.is_empty());
+            group.post(arc("alice"), arc("hello")).await.unwrap();
+            let (skipped, messages) = group.history(u64::MAX);
+            assert_eq!(skipped, 0);
+            assert!(messages.is_empty());
+            // the group is still usable, e.g. not poisoned.
+            assert_eq!(group.history(0).1.len(), 1);
+        })
+    }
+
+    #[test]
+    fn lagged_receiver() {
+        task::block_on(async {
+            let group = Group::open(arc("world"), &Config::default()).unwrap();
+            let mut rx = group.tx.new_receiver();
+            for i in 0..10 {
+                group.post(arc("alice"), arc(&i.to_string())).await.unwrap();
+            }
+            assert!(matches!(rx.recv().await, Err(RecvError::Overflowed(2))));
+            assert_eq!(rx.recv().await.unwrap().seq, 3);
+        })
+    }
+
+    #[test]
+    fn replay_on_join() {
+        task::block_on(async {
+            let addr = start().await;
+            let (mut alice, mut alice_rx) = connect(&addr).await;
+            let (mut bob, mut bob_rx) = connect(&addr).await;
+            send(&mut alice, "/nick alice").await;
+            next(&mut alice_rx).await;
+            send(&mut bob, "/nick bob").await;
+            next(&mut bob_rx).await;
+
+            send(&mut alice, "/join world").await;
+            for i in 1..=REPLAY_LEN as u64 + 2 {
+                send(&mut alice, &format!("world: {i}")).await;
+                assert_eq!(
+                    next(&mut alice_rx).await,
+                    message("world", i, "alice", &i.to_string())
+                );
+            }
+            send(&mut bob, "/join world").await;
+            for i in 3..=REPLAY_LEN as u64 + 2 {
+                assert_eq!(
+                    next(&mut bob_rx).await,
+                    message("world", i, "alice", &i.to_string())
+                );
+            }
+            send(&mut alice, "world: live").await;
+            let seq = REPLAY_LEN as u64 + 3;
+            assert_eq!(
+                next(&mut bob_rx).await,
+                message("world", seq, "alice", "live")
+            );
+
+            send(&mut bob, "/history world 10").await;
+            for i in 11..=REPLAY_LEN as u64 + 2 {
+                assert_eq!(
+                    next(&mut bob_rx).await,
+                    message("world", i, "alice", &i.to_string())
+                );
+            }
+            assert_eq!(
+                next(&mut bob_rx).await,
+                message("world", seq, "alice", "live")
+            );
+        })
+    }
+
+    #[test]
+    fn concurrent_posts_logged_in_order() {
+        task::block_on(async {
+            let dir = tempfile::tempdir().unwrap();
+            let config = Config {
+                data_dir: Some(dir.path().to_path_buf()),
+                ..Config::default()
+            };
+            let group = Arc::new(Group::open(arc("world"), &config).unwrap());
+            let posts: Vec<_> = (0..20)
+                .map(|i| {
+                    let group = group.clone();
+                    task::spawn(async move { group.post(arc("alice"), arc(&i.to_string())).await })
+                })
+                .collect();
+            for post in posts {
+                post.await.unwrap();
+            }
+            drop(group);
+
+            let group = Group::open(arc("world"), &config).unwrap();
+            let seqs: Vec<_> = group.history(0).1.iter().map(|m| m.seq).collect();
+            assert_eq!(seqs, (1..=20).collect::<Vec<_>>());
+        })
+    }
+
+    #[test]
+    fn replay_after_restart() {
+        task::block_on(async {
+            let dir = tempfile::tempdir().unwrap();
+            let config = Config {
+                data_dir: Some(dir.path().to_path_buf()),
+                segment_size: 100,
+                ..Config::default()
+            };
+            let group = Group::open(arc("a/../b c"), &config).unwrap();
+            for i in 1..=5 {
+                group.post(arc("alice"), arc(&i.to_string())).await.unwrap();
+            }
+            drop(group);
+
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let addr = listener.local_addr().unwrap().to_string();
+            task::spawn(serve(listener, config));
+            let (mut bob, mut bob_rx) = connect(&addr).await;
+            send(&mut bob, "/nick bob").await;
+            next(&mut bob_rx).await;
+            send(&mut bob, "/list").await;
+            assert_eq!(
+                next(&mut bob_rx).await,
+                Response::Groups {
+                    group_names: vec![arc("a/../b c")]
+                }
+            );
+            send(&mut bob, "/join a/../b c").await;
+            for i in 1..=5 {
+                assert_eq!(
+                    next(&mut bob_rx).await,
+                    message("a/../b c", i, "alice", &i.to_string())
+                );
+            }
+            // the name with the space is only reachable by the packet.
+            let req = Request::Post {
+                group_name: arc("a/../b c"),
+                message: arc("6"),
+            };
+            send_as_json(&mut bob, &req).await.unwrap();
+            assert_eq!(next(&mut bob_rx).await, message("a/../b c", 6, "bob", "6"));
+        })
+    }
+
+    #[test]
+    fn logged_group_kept() {
+        task::block_on(async {
+            let dir = tempfile::tempdir().unwrap();
+            let config = Config {
+                data_dir: Some(dir.path().to_path_buf()),
+                ..Config::default()
+            };
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let addr = listener.local_addr().unwrap().to_string();
+            task::spawn(serve(listener, config));
+            let (mut alice, mut alice_rx) = connect(&addr).await;
+            send(&mut alice, "/nick alice").await;
+            next(&mut alice_rx).await;
+            send(&mut alice, "/join world").await;
+            send(&mut alice, "world: 1").await;
+            assert_eq!(next(&mut alice_rx).await, message("world", 1, "alice", "1"));
+
+            // the last member leaves, but the log goes on.
+            send(&mut alice, "/leave world").await;
+            send(&mut alice, "/list").await;
+            assert_eq!(
+                next(&mut alice_rx).await,
+                Response::Groups {
+                    group_names: vec![arc("world")]
+                }
+            );
+            send(&mut alice, "/join world").await;
+            assert_eq!(next(&mut alice_rx).await, message("world", 1, "alice", "1"));
+            send(&mut alice, "world: 2").await;
+            assert_eq!(next(&mut alice_rx).await, message("world", 2, "alice", "2"));
+        })
+    }
+
+    #[test]
+    fn post_too_large() {
+        task::block_on(async {
+            let addr = start_with(Config {
+                max_frame_len: 100,
+                ..Config::default()
+            })
+            .await;
+            let (mut alice, mut alice_rx) = connect(&addr).await;
+            let (mut bob, mut bob_rx) = connect(&addr).await;
+            for (s, rx, name) in [
+                (&mut alice, &mut alice_rx, "alice"),
+                (&mut bob, &mut bob_rx, "bob"),
+            ] {
+                send(s, &format!("/nick {name}")).await;
+                next(rx).await;
+                send(s, "/join world").await;
+            }
+
+            // the post fits, but not the message with the sender and seq.
+            send(&mut alice, &format!("world {}", "x".repeat(50))).await;
+            assert!(is_error(&next(&mut alice_rx).await));
+            send(&mut alice, "world hi").await;
+            assert_eq!(
+                next(&mut alice_rx).await,
+                message("world", 1, "alice", "hi")
+            );
+            assert_eq!(next(&mut bob_rx).await, message("world", 1, "alice", "hi"));
+
+            // the response too large is replaced by the error.
+            for c in ['a', 'b', 'c'] {
+                send(&mut bob, &format!("/join {}", c.to_string().repeat(40))).await;
+            }
+            send(&mut bob, "/list").await;
+            assert!(is_error(&next(&mut bob_rx).await));
+            send(&mut bob, "/who world").await;
+            assert_eq!(
+                next(&mut bob_rx).await,
+                Response::Members {
+                    group_name: arc("world"),
+                    names: vec![arc("alice"), arc("bob")]
+                }
+            );
+        })
+    }
+
+    #[test]
+    fn length_prefixed_codec() {
+        task::block_on(async {
+            let addr = start().await;
+            let codec = AnyCodec::LengthPrefixed(LengthPrefixed::default());
+            let (mut alice, mut alice_rx) = connect_with(&addr, codec).await;
+            let (mut bob, mut bob_rx) = connect(&addr).await;
+            send(&mut bob, "/nick bob").await;
+            next(&mut bob_rx).await;
+            send(&mut bob, "/join world").await;
+
+            let req = Request::Login { name: arc("alice") };
+            send_packet(&codec, &mut alice, &req).await.unwrap();
+            next(&mut alice_rx).await;
+            let req = Request::Post {
+                group_name: arc("world"),
+                message: arc("two\nlines"),
+            };
+            send_packet(&codec, &mut alice, &req).await.unwrap();
+            assert_eq!(
+                next(&mut bob_rx).await,
+                message("world", 1, "alice", "two\nlines")
+            );
+        })
+    }
+
+    #[test]
+    fn reject_unsupported_codec() {
+        task::block_on(async {
+            let addr = start().await;
+            let mut s = TcpStream::connect(&addr).await.unwrap();
+            s.write_all(b"async-chat cbor\n").await.unwrap();
+            let mut reply = String::new();
+            s.read_to_string(&mut reply).await.unwrap();
+            assert!(reply.starts_with("async-chat error:"), "{reply}");
+        })
+    }
+
+    #[test]
+    fn max_frame_len() {
+        task::block_on(async {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let addr = listener.local_addr().unwrap().to_string();
+            let config = Config {
+                max_frame_len: 64,
+                ..Config::default()
+            };
+            task::spawn(serve(listener, config));
+            let (mut alice, mut alice_rx) = connect(&addr).await;
+            send(&mut alice, "/nick alice").await;
+            next(&mut alice_rx).await;
+            let long = "x".repeat(100);
+            let req = Request::Login { name: arc(&long) };
+            send_as_json(&mut alice, &req).await.unwrap();
+            // reported, and closed.
+            assert!(is_error(&next(&mut alice_rx).await));
+            assert!(alice_rx.next().await.is_none());
+        })
+    }
 }
@@ -1,4 +1,4 @@
-use async_chat::server;
+use async_chat::{server, Config};
 use futures_lite::future;
 
 const ADDR: &str = "localhost:8080";
@@ -6,8 +6,12 @@ const ADDR: &str = "localhost:8080";
 fn main() {
     tracing_subscriber::fmt::init();
     let addr = std::env::args().nth(1).unwrap_or_else(|| ADDR.to_string());
+    let config = Config {
+        data_dir: std::env::args().nth(2).map(Into::into),
+        ..Config::default()
+    };
 
-    if let Err(e) = future::block_on(server(addr)) {
+    if let Err(e) = future::block_on(server(addr, config)) {
         eprintln!("server: {e}");
     }
 }
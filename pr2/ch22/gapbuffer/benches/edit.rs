@@ -0,0 +1,67 @@
+//! Random position edits over [`GapBuffer`] against [`Rope`].
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use gapbuffer::{EditBuffer, GapBuffer, Rope};
+
+// pseudo random positions with xorshift.
+fn positions(count: usize) -> Vec<usize> {
+    let mut seed = 0x2545_f491_u32;
+    (0..count)
+        .map(|_| {
+            seed ^= seed << 13;
+            seed ^= seed >> 17;
+            seed ^= seed << 5;
+            seed as usize
+        })
+        .collect()
+}
+
+// inserts and removes at the random positions, keeping the length.
+fn edit<B: EditBuffer<u8>>(buf: &mut B, positions: &[usize]) {
+    for pos in positions {
+        let index = pos % (buf.len() + 1);
+        buf.insert_at(index, b'x');
+        let index = pos.rotate_left(16) % buf.len();
+        black_box(buf.remove_at(index));
+    }
+}
+
+fn random_edit(c: &mut Criterion) {
+    let mut group = c.benchmark_group("random_edit");
+    let positions = positions(1_000);
+    for len in [10_000, 1_000_000] {
+        let mut gap = GapBuffer::new();
+        gap.insert_iter((0..len).map(|i| i as u8));
+        let mut rope: Rope<u8> = (0..len).map(|i| i as u8).collect();
+        group.bench_function(BenchmarkId::new("GapBuffer", len), |b| {
+            b.iter(|| edit(&mut gap, &positions))
+        });
+        group.bench_function(BenchmarkId::new("Rope", len), |b| {
+            b.iter(|| edit(&mut rope, &positions))
+        });
+    }
+    group.finish();
+}
+
+fn sequential_typing(c: &mut Criterion) {
+    let mut group = c.benchmark_group("sequential_typing");
+    let len = 1_000_000;
+    let mut gap = GapBuffer::new();
+    gap.insert_iter((0..len).map(|i| i as u8));
+    let mut rope: Rope<u8> = (0..len).map(|i| i as u8).collect();
+    group.bench_function("GapBuffer", |b| {
+        b.iter(|| {
+            gap.insert_iter_at(len / 2, [b'x'; 100]);
+            gap.delete_range(len / 2..len / 2 + 100);
+        })
+    });
+    group.bench_function("Rope", |b| {
+        b.iter(|| {
+            rope.insert_iter_at(len / 2, [b'x'; 100]);
+            rope.delete_range(len / 2..len / 2 + 100);
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, random_edit, sequential_typing);
+criterion_main!(benches);
@@ -0,0 +1,397 @@
+//! Multiple cursors and selections over [`GapBuffer`].
+//!
+//! Every edit shifts all the selections after it, and the overlapping
+//! selections are merged into one, as the editors do.
+use super::GapBuffer;
+use std::cmp::Ordering;
+use std::fmt::{self, Debug};
+use std::ops::Range;
+use tracing::instrument;
+
+/// Selection from `anchor` to `head`, the cursor.  It's just a cursor
+/// if both are the same.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Selection {
+    pub anchor: usize,
+    pub head: usize,
+}
+
+impl Selection {
+    pub fn cursor(pos: usize) -> Self {
+        Self {
+            anchor: pos,
+            head: pos,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.anchor == self.head
+    }
+
+    pub fn range(&self) -> Range<usize> {
+        self.anchor.min(self.head)..self.anchor.max(self.head)
+    }
+
+    // shifts the positions at or after `pos` by the `n` inserted
+    // elements.
+    fn shift_insert(&mut self, pos: usize, n: usize) {
+        for p in [&mut self.anchor, &mut self.head] {
+            if *p >= pos {
+                *p += n;
+            }
+        }
+    }
+
+    // moves the positions in `range` to its start, and shifts the ones
+    // after it.
+    fn shift_delete(&mut self, range: &Range<usize>) {
+        for p in [&mut self.anchor, &mut self.head] {
+            if *p >= range.end {
+                *p -= range.len();
+            } else if *p > range.start {
+                *p = range.start;
+            }
+        }
+    }
+}
+
+pub struct MultiCursor<T> {
+    buf: GapBuffer<T>,
+    // sorted by the start, without overlaps.
+    selections: Vec<Selection>,
+}
+
+impl<T> Default for MultiCursor<T> {
+    fn default() -> Self {
+        Self::from(GapBuffer::new())
+    }
+}
+
+impl<T> From<GapBuffer<T>> for MultiCursor<T> {
+    /// Starts with the single cursor at the buffer position.
+    fn from(buf: GapBuffer<T>) -> Self {
+        let selections = vec![Selection::cursor(buf.position())];
+        Self { buf, selections }
+    }
+}
+
+impl<T> Debug for MultiCursor<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MultiCursor")
+            .field("buf", &self.buf)
+            .field("selections", &self.selections)
+            .finish()
+    }
+}
+
+impl<T> MultiCursor<T> {
+    #[instrument(name = "MultiCursor::new")]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn buffer(&self) -> &GapBuffer<T> {
+        &self.buf
+    }
+
+    pub fn into_inner(self) -> GapBuffer<T> {
+        self.buf
+    }
+
+    /// Returns the selections sorted by the position.
+    pub fn selections(&self) -> &[Selection] {
+        &self.selections
+    }
+
+    /// panic if `pos` is out of bounds.
+    #[instrument(name = "MultiCursor::add_cursor")]
+    pub fn add_cursor(&mut self, pos: usize) {
+        self.add_selection(pos, pos);
+    }
+
+    /// Adds the selection, merged with the overlapping ones.
+    ///
+    /// panic if `anchor` or `head` is out of bounds.
+    #[instrument(name = "MultiCursor::add_selection")]
+    pub fn add_selection(&mut self, anchor: usize, head: usize) {
+        let len = self.buf.len();
+        if anchor > len || head > len {
+            panic!(
+                "selection {}..{} out of range for MultiCursor",
+                anchor, head
+            );
+        }
+        self.selections.push(Selection { anchor, head });
+        self.normalize();
+    }
+
+    /// Replaces all the selections with the single cursor.
+    ///
+    /// panic if `pos` is out of bounds.
+    #[instrument(name = "MultiCursor::set_cursor")]
+    pub fn set_cursor(&mut self, pos: usize) {
+        if pos > self.buf.len() {
+            panic!("index {} out of range for MultiCursor", pos);
+        }
+        self.selections = vec![Selection::cursor(pos)];
+    }
+
+    /// Inserts `vals` at `pos`, shifting the selections.
+    ///
+    /// panic if `pos` is out of bounds.
+    #[instrument(name = "MultiCursor::insert_at", skip(vals))]
+    pub fn insert_at(&mut self, pos: usize, vals: &[T])
+    where
+        T: Clone,
+    {
+        self.buf.set_position(pos);
+        self.buf.insert_iter(vals.iter().cloned());
+        for selection in &mut self.selections {
+            selection.shift_insert(pos, vals.len());
+        }
+        self.normalize();
+    }
+
+    /// Deletes `range`, shifting the selections.
+    ///
+    /// panic if `range` is out of bounds.
+    #[instrument(name = "MultiCursor::delete_range")]
+    pub fn delete_range(&mut self, range: Range<usize>) {
+        self.buf.delete_range(range.clone());
+        for selection in &mut self.selections {
+            selection.shift_delete(&range);
+        }
+        self.normalize();
+    }
+
+    /// Types `vals` at all the cursors, replacing the selected elements.
+    #[instrument(name = "MultiCursor::insert_at_all_cursors", skip(vals))]
+    pub fn insert_at_all_cursors(&mut self, vals: &[T])
+    where
+        T: Clone,
+    {
+        // from the last one, not to shift the ones to edit.
+        for i in (0..self.selections.len()).rev() {
+            let range = self.selections[i].range();
+            self.buf.delete_range(range.clone());
+            self.buf.insert_iter(vals.iter().cloned());
+            for selection in &mut self.selections[i..] {
+                selection.shift_delete(&range);
+                selection.shift_insert(range.start, vals.len());
+            }
+        }
+        self.normalize();
+    }
+
+    /// Deletes the selected elements, or the element before the cursor
+    /// for the empty selection, like the backspace.
+    #[instrument(name = "MultiCursor::delete_at_all_cursors")]
+    pub fn delete_at_all_cursors(&mut self) {
+        for i in (0..self.selections.len()).rev() {
+            let selection = self.selections[i];
+            let range = if selection.is_empty() {
+                selection.head.saturating_sub(1)..selection.head
+            } else {
+                selection.range()
+            };
+            self.buf.delete_range(range.clone());
+            // the previous selection may contain the element before the
+            // cursor.
+            for selection in &mut self.selections {
+                selection.shift_delete(&range);
+            }
+        }
+        self.normalize();
+    }
+
+    // sorts and merges the overlapping selections, and the cursors at the
+    // same position.  The merged selection keeps the direction of the
+    // first one.
+    fn normalize(&mut self) {
+        self.selections
+            .sort_by_key(|selection| (selection.range().start, selection.range().end));
+        let mut merged: Vec<Selection> = Vec::with_capacity(self.selections.len());
+        for selection in self.selections.drain(..) {
+            let last = match merged.last_mut() {
+                Some(last) if overlaps(&last.range(), &selection.range()) => last,
+                _ => {
+                    merged.push(selection);
+                    continue;
+                }
+            };
+            let start = last.range().start;
+            let end = last.range().end.max(selection.range().end);
+            *last = match last.anchor.cmp(&last.head) {
+                Ordering::Greater => Selection {
+                    anchor: end,
+                    head: start,
+                },
+                _ => Selection {
+                    anchor: start,
+                    head: end,
+                },
+            };
+        }
+        self.selections = merged;
+    }
+}
+
+// the ranges share any element, or the cursors are at the same position.
+fn overlaps(a: &Range<usize>, b: &Range<usize>) -> bool {
+    a.start < b.end && b.start < a.end
+        || a == b
+        || (a.is_empty() && b.contains(&a.start))
+        || (b.is_empty() && a.contains(&b.start))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MultiCursor, Selection};
+    use crate::GapBuffer;
+
+    fn from_str(s: &str) -> MultiCursor<char> {
+        let mut buf = GapBuffer::new();
+        buf.insert_iter(s.chars());
+        buf.set_position(0);
+        MultiCursor::from(buf)
+    }
+
+    fn contents(cursors: &MultiCursor<char>) -> String {
+        cursors.buffer().iter().collect()
+    }
+
+    fn chars(s: &str) -> Vec<char> {
+        s.chars().collect()
+    }
+
+    fn cursors(cursors: &MultiCursor<char>) -> Vec<usize> {
+        cursors.selections().iter().map(|s| s.head).collect()
+    }
+
+    #[test]
+    fn insert_at_all_cursors() {
+        let mut mc = from_str("a\nb\nc");
+        mc.add_cursor(2);
+        mc.add_cursor(4);
+        mc.insert_at_all_cursors(&chars("- "));
+        assert_eq!(contents(&mc), "- a\n- b\n- c");
+        assert_eq!(cursors(&mc), [2, 6, 10]);
+        mc.insert_at_all_cursors(&chars("x"));
+        assert_eq!(contents(&mc), "- xa\n- xb\n- xc");
+        assert_eq!(cursors(&mc), [3, 8, 13]);
+    }
+
+    #[test]
+    fn delete_at_all_cursors() {
+        let mut mc = from_str("ab\ncd\nef");
+        mc.set_cursor(2);
+        mc.add_cursor(5);
+        mc.add_cursor(8);
+        mc.delete_at_all_cursors();
+        assert_eq!(contents(&mc), "a\nc\ne");
+        assert_eq!(cursors(&mc), [1, 3, 5]);
+        mc.delete_at_all_cursors();
+        assert_eq!(contents(&mc), "\n\n");
+        assert_eq!(cursors(&mc), [0, 1, 2]);
+        mc.delete_at_all_cursors();
+        // the first cursor at 0 deletes nothing, and the others meet it.
+        assert_eq!(contents(&mc), "");
+        assert_eq!(cursors(&mc), [0]);
+    }
+
+    #[test]
+    fn replace_selections() {
+        let mut mc = from_str("foo bar foo");
+        mc.set_cursor(0);
+        mc.add_selection(0, 3);
+        mc.add_selection(11, 8);
+        assert_eq!(mc.selections().len(), 2);
+        mc.insert_at_all_cursors(&chars("baz"));
+        assert_eq!(contents(&mc), "baz bar baz");
+        assert_eq!(cursors(&mc), [3, 11]);
+        assert!(mc.selections().iter().all(Selection::is_empty));
+    }
+
+    #[test]
+    fn overlapping_selections() {
+        let mut mc = from_str("0123456789");
+        mc.set_cursor(0);
+        mc.add_selection(1, 4);
+        mc.add_selection(3, 6);
+        // merged with the direction of the first one.
+        assert_eq!(
+            mc.selections(),
+            [Selection::cursor(0), Selection { anchor: 1, head: 6 }]
+        );
+        mc.add_selection(9, 5);
+        assert_eq!(mc.selections()[1], Selection { anchor: 1, head: 9 });
+        // touching selections are kept apart.
+        mc.add_selection(9, 10);
+        assert_eq!(mc.selections().len(), 3);
+        // a cursor inside the selection is merged.
+        mc.add_cursor(4);
+        assert_eq!(mc.selections().len(), 3);
+        mc.delete_at_all_cursors();
+        assert_eq!(contents(&mc), "0");
+        assert_eq!(cursors(&mc), [0, 1]);
+    }
+
+    #[test]
+    fn cursor_right_after_selection() {
+        let mut mc = from_str("abcdef");
+        mc.set_cursor(3);
+        mc.add_selection(0, 3);
+        mc.delete_at_all_cursors();
+        assert_eq!(contents(&mc), "def");
+        assert_eq!(cursors(&mc), [0]);
+
+        let mut mc = from_str("abcdef");
+        mc.set_cursor(3);
+        mc.add_selection(1, 3);
+        mc.insert_at_all_cursors(&chars("X"));
+        assert_eq!(contents(&mc), "aXXdef");
+        assert_eq!(cursors(&mc), [2, 3]);
+    }
+
+    #[test]
+    fn selections_meet_after_delete() {
+        let mut mc = from_str("aXbXc");
+        mc.set_cursor(1);
+        mc.add_selection(2, 3);
+        mc.add_selection(3, 4);
+        mc.delete_range(1..4);
+        assert_eq!(contents(&mc), "ac");
+        assert_eq!(mc.selections(), [Selection::cursor(1)]);
+    }
+
+    #[test]
+    fn edits_shift_cursors() {
+        let mut mc = from_str("hello world");
+        mc.set_cursor(5);
+        mc.add_selection(6, 11);
+        mc.insert_at(0, &chars(">> "));
+        assert_eq!(
+            mc.selections(),
+            [
+                Selection::cursor(8),
+                Selection {
+                    anchor: 9,
+                    head: 14
+                }
+            ]
+        );
+        mc.delete_range(0..3);
+        mc.insert_at(11, &chars("!"));
+        assert_eq!(contents(&mc), "hello world!");
+        assert_eq!(
+            mc.selections(),
+            [
+                Selection::cursor(5),
+                Selection {
+                    anchor: 6,
+                    head: 12
+                }
+            ]
+        );
+    }
+}
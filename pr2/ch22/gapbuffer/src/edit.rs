@@ -0,0 +1,72 @@
+//! Common editing interface of [`GapBuffer`] and [`Rope`].
+//!
+//! [`Rope`]: crate::Rope
+use super::GapBuffer;
+use std::ops::Range;
+
+/// Editing by the index, instead of the cursor.
+pub trait EditBuffer<T> {
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn get(&self, index: usize) -> Option<&T>;
+
+    /// panic if `index` is out of bounds.
+    fn insert_at(&mut self, index: usize, val: T);
+
+    /// Inserts `iterable` at `index`, in order.
+    ///
+    /// panic if `index` is out of bounds.
+    fn insert_iter_at<I>(&mut self, index: usize, iterable: I)
+    where
+        I: IntoIterator<Item = T>,
+        Self: Sized,
+    {
+        for (i, val) in iterable.into_iter().enumerate() {
+            self.insert_at(index + i, val);
+        }
+    }
+
+    fn remove_at(&mut self, index: usize) -> Option<T>;
+
+    /// panic if `range` is out of bounds.
+    fn delete_range(&mut self, range: Range<usize>);
+}
+
+impl<T> EditBuffer<T> for GapBuffer<T> {
+    fn len(&self) -> usize {
+        GapBuffer::len(self)
+    }
+
+    fn get(&self, index: usize) -> Option<&T> {
+        GapBuffer::get(self, index)
+    }
+
+    fn insert_at(&mut self, index: usize, val: T) {
+        self.set_position(index);
+        self.insert(val);
+    }
+
+    fn insert_iter_at<I>(&mut self, index: usize, iterable: I)
+    where
+        I: IntoIterator<Item = T>,
+    {
+        self.set_position(index);
+        self.insert_iter(iterable);
+    }
+
+    fn remove_at(&mut self, index: usize) -> Option<T> {
+        if index >= GapBuffer::len(self) {
+            return None;
+        }
+        self.set_position(index);
+        self.remove()
+    }
+
+    fn delete_range(&mut self, range: Range<usize>) {
+        GapBuffer::delete_range(self, range)
+    }
+}
@@ -0,0 +1,581 @@
+//! Undo/redo edit log around [`GapBuffer`].
+//!
+//! Every edit is recorded as an `Edit`, grouped into transactions.
+//! Reverting an edit returns the edit to revert it back, so that undo
+//! and redo are the same operation over the two stacks and no `Clone`
+//! bound is needed on `T`.
+use super::GapBuffer;
+use std::collections::VecDeque;
+use std::fmt::{self, Debug};
+use std::ops::{Deref, DerefMut, Range};
+use tracing::instrument;
+
+/// Default number of the transactions to keep in the history.
+pub const DEFAULT_LIMIT: usize = 1000;
+
+pub struct History<T> {
+    buf: GapBuffer<T>,
+    undo: VecDeque<Transaction<T>>,
+    redo: Vec<Transaction<T>>,
+    // transaction in progress, with the nesting depth.
+    current: Option<Transaction<T>>,
+    depth: usize,
+    // the last undo transaction is the run of typing to extend.
+    typing: bool,
+    limit: usize,
+}
+
+enum Edit<T> {
+    /// `len` elements inserted at `pos`.
+    Insert {
+        pos: usize,
+        len: usize,
+        forward: bool,
+    },
+    /// `vals` deleted at `pos`.  `forward` keeps the cursor at `pos`
+    /// while the elements are in place, e.g. the forward delete, or right
+    /// after them otherwise, e.g. the typing.
+    Delete {
+        pos: usize,
+        vals: Vec<T>,
+        forward: bool,
+    },
+    /// Cursor moved from `from` to `to`.
+    Move { from: usize, to: usize },
+}
+
+struct Transaction<T> {
+    edits: Vec<Edit<T>>,
+}
+
+struct TransactionGuard<'a, T>(&'a mut History<T>);
+
+impl<T> Drop for TransactionGuard<'_, T> {
+    fn drop(&mut self) {
+        self.0.end_transaction();
+    }
+}
+
+impl<T> Deref for TransactionGuard<'_, T> {
+    type Target = History<T>;
+
+    fn deref(&self) -> &Self::Target {
+        self.0
+    }
+}
+
+impl<T> DerefMut for TransactionGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.0
+    }
+}
+
+impl<T> Default for History<T> {
+    fn default() -> Self {
+        Self::with_limit(DEFAULT_LIMIT)
+    }
+}
+
+impl<T> Debug for History<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("History")
+            .field("buf", &self.buf)
+            .field("undo.len", &self.undo.len())
+            .field("redo.len", &self.redo.len())
+            .field("depth", &self.depth)
+            .field("limit", &self.limit)
+            .finish()
+    }
+}
+
+impl<T> From<GapBuffer<T>> for History<T> {
+    fn from(buf: GapBuffer<T>) -> Self {
+        Self {
+            buf,
+            ..Self::default()
+        }
+    }
+}
+
+impl<T> Edit<T> {
+    // applies the inverse of the edit, and returns the edit to revert it
+    // back.
+    fn revert(self, buf: &mut GapBuffer<T>) -> Self {
+        match self {
+            Self::Insert { pos, len, forward } => {
+                buf.set_position(pos);
+                let vals = (0..len).filter_map(|_| buf.remove()).collect();
+                Self::Delete { pos, vals, forward }
+            }
+            Self::Delete { pos, vals, forward } => {
+                let len = vals.len();
+                buf.set_position(pos);
+                buf.insert_iter(vals);
+                if forward {
+                    buf.set_position(pos);
+                }
+                Self::Insert { pos, len, forward }
+            }
+            Self::Move { from, to } => {
+                buf.set_position(from);
+                Self::Move { from: to, to: from }
+            }
+        }
+    }
+}
+
+impl<T> Transaction<T> {
+    // the reverted edits are in the reverse order, to revert back.
+    fn revert(self, buf: &mut GapBuffer<T>) -> Self {
+        let edits = self
+            .edits
+            .into_iter()
+            .rev()
+            .map(|e| e.revert(buf))
+            .collect();
+        Self { edits }
+    }
+}
+
+impl<T> History<T> {
+    #[instrument(name = "History::new")]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Keeps up to `limit` transactions to undo.
+    #[instrument(name = "History::with_limit")]
+    pub fn with_limit(limit: usize) -> Self {
+        Self {
+            buf: GapBuffer::new(),
+            undo: VecDeque::new(),
+            redo: vec![],
+            current: None,
+            depth: 0,
+            typing: false,
+            limit,
+        }
+    }
+
+    /// Returns the buffer, to read the contents.
+    pub fn buffer(&self) -> &GapBuffer<T> {
+        &self.buf
+    }
+
+    pub fn into_inner(self) -> GapBuffer<T> {
+        self.buf
+    }
+
+    #[instrument(name = "History::position")]
+    pub fn position(&self) -> usize {
+        self.buf.position()
+    }
+
+    #[instrument(name = "History::can_undo")]
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+
+    #[instrument(name = "History::can_redo")]
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+
+    /// Types `val` at the cursor.  Consecutive typing outside of the
+    /// transaction is coalesced into a single transaction, until the
+    /// other edit, cursor move, undo or redo.
+    #[instrument(name = "History::insert", skip(val))]
+    pub fn insert(&mut self, val: T) {
+        let pos = self.buf.position();
+        self.buf.insert(val);
+        if self.depth == 0 && self.typing {
+            if let Some(Edit::Insert {
+                pos: start, len, ..
+            }) = self
+                .undo
+                .back_mut()
+                .and_then(|transaction| transaction.edits.last_mut())
+            {
+                if *start + *len == pos {
+                    *len += 1;
+                    return;
+                }
+            }
+        }
+        self.record(Edit::Insert {
+            pos,
+            len: 1,
+            forward: false,
+        });
+        self.typing = self.depth == 0;
+    }
+
+    #[instrument(name = "History::insert_iter", skip(iterable))]
+    pub fn insert_iter<I>(&mut self, iterable: I)
+    where
+        I: IntoIterator<Item = T>,
+    {
+        let pos = self.buf.position();
+        self.buf.insert_iter(iterable);
+        let len = self.buf.position() - pos;
+        if len > 0 {
+            self.record(Edit::Insert {
+                pos,
+                len,
+                forward: false,
+            });
+        }
+    }
+
+    /// Removes the element right after the cursor.
+    #[instrument(name = "History::remove")]
+    pub fn remove(&mut self) -> bool {
+        let pos = self.buf.position();
+        match self.buf.remove() {
+            None => false,
+            Some(val) => {
+                self.record(Edit::Delete {
+                    pos,
+                    vals: vec![val],
+                    forward: true,
+                });
+                true
+            }
+        }
+    }
+
+    /// Removes the element right before the cursor.
+    #[instrument(name = "History::backspace")]
+    pub fn backspace(&mut self) -> bool {
+        match self.buf.backspace() {
+            None => false,
+            Some(val) => {
+                let pos = self.buf.position();
+                self.record(Edit::Delete {
+                    pos,
+                    vals: vec![val],
+                    forward: false,
+                });
+                true
+            }
+        }
+    }
+
+    /// panic if `range` is out of bounds.
+    #[instrument(name = "History::delete_range")]
+    pub fn delete_range(&mut self, range: Range<usize>) {
+        if range.start > range.end || range.end > self.buf.len() {
+            panic!("range {:?} out of range for History", range);
+        }
+        self.set_position(range.start);
+        let vals: Vec<_> = range.filter_map(|_| self.buf.remove()).collect();
+        if !vals.is_empty() {
+            self.record(Edit::Delete {
+                pos: self.buf.position(),
+                vals,
+                forward: true,
+            });
+        }
+    }
+
+    /// panic if `range` is out of bounds.
+    #[instrument(name = "History::replace_range", skip(iterable))]
+    pub fn replace_range<I>(&mut self, range: Range<usize>, iterable: I)
+    where
+        I: IntoIterator<Item = T>,
+    {
+        self.transaction(|history| {
+            history.delete_range(range);
+            history.insert_iter(iterable);
+        });
+    }
+
+    /// Moves the cursor.  The move is recorded only in the transaction,
+    /// to restore the cursor on undo, but not as an undo step by itself.
+    ///
+    /// panic if `pos` is out of bounds.
+    #[instrument(name = "History::set_position")]
+    pub fn set_position(&mut self, pos: usize) {
+        let from = self.buf.position();
+        self.buf.set_position(pos);
+        self.typing = false;
+        if self.depth > 0 && from != pos {
+            self.record(Edit::Move { from, to: pos });
+        }
+    }
+
+    #[instrument(name = "History::move_forward")]
+    pub fn move_forward(&mut self) -> bool {
+        let pos = self.buf.position();
+        if pos == self.buf.len() {
+            return false;
+        }
+        self.set_position(pos + 1);
+        true
+    }
+
+    #[instrument(name = "History::move_back")]
+    pub fn move_back(&mut self) -> bool {
+        let pos = self.buf.position();
+        if pos == 0 {
+            return false;
+        }
+        self.set_position(pos - 1);
+        true
+    }
+
+    /// Groups the edits in `f` into a single transaction, to undo and
+    /// redo at once.  The nested transactions are merged into the
+    /// outermost one.
+    #[instrument(name = "History::transaction", skip(f))]
+    pub fn transaction<F, R>(&mut self, f: F) -> R
+    where
+        F: FnOnce(&mut Self) -> R,
+    {
+        if self.depth == 0 {
+            self.current = Some(Transaction { edits: vec![] });
+            self.typing = false;
+        }
+        self.depth += 1;
+        let mut guard = TransactionGuard(self);
+        f(&mut guard)
+    }
+
+    // closes the transaction, also when `f` panicked, keeping the edits
+    // made so far undoable.
+    fn end_transaction(&mut self) {
+        self.depth -= 1;
+        if self.depth == 0 {
+            if let Some(transaction) = self.current.take() {
+                if transaction
+                    .edits
+                    .iter()
+                    .any(|e| !matches!(e, Edit::Move { .. }))
+                {
+                    self.push(transaction);
+                }
+            }
+        }
+    }
+
+    /// Reverts the last transaction.  Returns false if there is nothing
+    /// to undo.
+    ///
+    /// panic if called in the transaction.
+    #[instrument(name = "History::undo")]
+    pub fn undo(&mut self) -> bool {
+        assert_eq!(self.depth, 0, "undo in the transaction");
+        self.typing = false;
+        match self.undo.pop_back() {
+            None => false,
+            Some(transaction) => {
+                self.redo.push(transaction.revert(&mut self.buf));
+                true
+            }
+        }
+    }
+
+    /// Re-applies the last undone transaction.  Returns false if there
+    /// is nothing to redo.
+    ///
+    /// panic if called in the transaction.
+    #[instrument(name = "History::redo")]
+    pub fn redo(&mut self) -> bool {
+        assert_eq!(self.depth, 0, "redo in the transaction");
+        self.typing = false;
+        match self.redo.pop() {
+            None => false,
+            Some(transaction) => {
+                self.undo.push_back(transaction.revert(&mut self.buf));
+                true
+            }
+        }
+    }
+
+    fn record(&mut self, edit: Edit<T>) {
+        self.typing = false;
+        match self.current.as_mut() {
+            Some(transaction) => transaction.edits.push(edit),
+            None => self.push(Transaction { edits: vec![edit] }),
+        }
+    }
+
+    fn push(&mut self, transaction: Transaction<T>) {
+        self.redo.clear();
+        self.undo.push_back(transaction);
+        while self.undo.len() > self.limit {
+            self.undo.pop_front();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::History;
+    use std::panic::{self, AssertUnwindSafe};
+    use std::rc::Rc;
+
+    fn contents(history: &History<char>) -> String {
+        history.buffer().iter().collect()
+    }
+
+    fn type_str(history: &mut History<char>, s: &str) {
+        s.chars().for_each(|c| history.insert(c));
+    }
+
+    #[test]
+    fn coalesce_typing() {
+        let mut history = History::new();
+        type_str(&mut history, "hello");
+        history.insert_iter(" big".chars());
+        type_str(&mut history, " world");
+        assert_eq!(contents(&history), "hello big world");
+        assert!(history.undo());
+        assert_eq!(contents(&history), "hello big");
+        assert!(history.undo());
+        assert_eq!(contents(&history), "hello");
+        assert!(history.undo());
+        assert_eq!(contents(&history), "");
+        assert!(!history.undo());
+        assert!(history.redo());
+        assert!(history.redo());
+        assert!(history.redo());
+        assert!(!history.redo());
+        assert_eq!(contents(&history), "hello big world");
+        assert_eq!(history.position(), 15);
+    }
+
+    #[test]
+    fn move_breaks_typing() {
+        let mut history = History::new();
+        type_str(&mut history, "ac");
+        history.move_back();
+        type_str(&mut history, "b");
+        history.move_forward();
+        type_str(&mut history, "d");
+        assert_eq!(contents(&history), "abcd");
+        history.undo();
+        assert_eq!(contents(&history), "abc");
+        assert_eq!(history.position(), 3);
+        history.undo();
+        assert_eq!(contents(&history), "ac");
+        assert_eq!(history.position(), 1);
+        history.undo();
+        assert_eq!(contents(&history), "");
+    }
+
+    #[test]
+    fn deletes() {
+        let mut history = History::new();
+        type_str(&mut history, "abcdef");
+        history.set_position(3);
+        assert!(history.backspace());
+        assert!(history.remove());
+        assert_eq!(contents(&history), "abef");
+        history.delete_range(0..1);
+        assert_eq!(contents(&history), "bef");
+        history.undo();
+        assert_eq!(contents(&history), "abef");
+        assert_eq!(history.position(), 0);
+        history.undo();
+        assert_eq!(contents(&history), "abdef");
+        assert_eq!(history.position(), 2);
+        history.undo();
+        assert_eq!(contents(&history), "abcdef");
+        assert_eq!(history.position(), 3);
+        history.redo();
+        assert_eq!(contents(&history), "abdef");
+        assert_eq!(history.position(), 2);
+        history.set_position(5);
+        assert!(!history.remove());
+        history.set_position(0);
+        assert!(!history.backspace());
+        history.redo();
+        assert_eq!(contents(&history), "abef");
+    }
+
+    #[test]
+    fn transaction() {
+        let mut history = History::new();
+        type_str(&mut history, "hello world");
+        history.transaction(|history| {
+            history.set_position(0);
+            history.remove();
+            history.insert('H');
+            history.transaction(|history| history.replace_range(6..11, "World".chars()));
+            history.set_position(11);
+            history.insert('!');
+        });
+        assert_eq!(contents(&history), "Hello World!");
+        history.undo();
+        assert_eq!(contents(&history), "hello world");
+        assert_eq!(history.position(), 11);
+        history.redo();
+        assert_eq!(contents(&history), "Hello World!");
+        assert_eq!(history.position(), 12);
+        // moves only is not a transaction.
+        history.transaction(|history| history.set_position(0));
+        history.undo();
+        assert_eq!(contents(&history), "hello world");
+    }
+
+    #[test]
+    fn transaction_panic() {
+        let mut history = History::new();
+        type_str(&mut history, "hello");
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            history.transaction(|history| {
+                history.transaction(|history| history.insert_iter(" world".chars()));
+                panic!("in the transaction");
+            })
+        }));
+        assert!(result.is_err());
+        assert_eq!(contents(&history), "hello world");
+        // the transaction is closed, with the edits made before the panic.
+        history.undo();
+        assert_eq!(contents(&history), "hello");
+        type_str(&mut history, "!");
+        history.undo();
+        assert_eq!(contents(&history), "hello");
+    }
+
+    #[test]
+    fn edit_clears_redo() {
+        let mut history = History::new();
+        type_str(&mut history, "ab");
+        history.undo();
+        assert!(history.can_redo());
+        type_str(&mut history, "c");
+        assert!(!history.can_redo());
+        assert!(!history.redo());
+        assert_eq!(contents(&history), "c");
+    }
+
+    #[test]
+    fn limit() {
+        let mut history = History::with_limit(3);
+        for c in "abcde".chars() {
+            history.insert_iter([c]);
+        }
+        while history.undo() {}
+        assert_eq!(contents(&history), "ab");
+        while history.redo() {}
+        assert_eq!(contents(&history), "abcde");
+    }
+
+    #[test]
+    fn drop_history() {
+        let rc = Rc::new(());
+        let mut history = History::new();
+        history.insert_iter((0..10).map(|_| rc.clone()));
+        history.delete_range(2..6);
+        history.undo();
+        history.undo();
+        assert_eq!(Rc::strong_count(&rc), 11);
+        history.redo();
+        history.redo();
+        assert_eq!(Rc::strong_count(&rc), 11);
+        drop(history);
+        assert_eq!(Rc::strong_count(&rc), 1);
+    }
+}
@@ -3,13 +3,51 @@
 //! Example demonstrated in [Programming Rust] 2nd Edition, page 651.
 use std::cmp::Ordering;
 use std::fmt::{self, Debug};
-use std::ops::Range;
-use std::ptr::{copy, copy_nonoverlapping, drop_in_place, write};
+use std::mem;
+use std::ops::{Index, IndexMut, Range};
+use std::ptr::{copy, copy_nonoverlapping, drop_in_place, read, write};
+use std::slice;
 use tracing::{debug, instrument, trace};
 
+mod cursors;
+mod edit;
+mod history;
+mod iter;
+mod rope;
+mod search;
+mod text;
+
+pub use cursors::{MultiCursor, Selection};
+pub use edit::EditBuffer;
+pub use history::History;
+pub use iter::{IntoIter, Iter, IterMut};
+pub use rope::Rope;
+pub use search::Matches;
+pub use text::TextBuffer;
+
 pub struct GapBuffer<T> {
     buf: Vec<T>,
     gap: Range<usize>,
+    growth: Growth,
+}
+
+/// Growth policy of the capacity, when the gap is full.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Growth {
+    /// Doubles the capacity, starting at 4.
+    #[default]
+    Double,
+    /// Adds the fixed number of elements, at least one.
+    Linear(usize),
+}
+
+impl Growth {
+    fn next_capacity(self, capacity: usize) -> usize {
+        match self {
+            Self::Double => (capacity * 2).max(4),
+            Self::Linear(n) => capacity + n.max(1),
+        }
+    }
 }
 
 impl<T> Drop for GapBuffer<T> {
@@ -36,6 +74,7 @@ impl<T> Debug for GapBuffer<T> {
             .field("buf.capacity", &self.capacity())
             .field("gap", &self.gap)
             .field("gap.len", &self.gap.len())
+            .field("growth", &self.growth)
             .finish()
     }
 }
@@ -45,6 +84,32 @@ impl<T> Default for GapBuffer<T> {
         Self {
             buf: vec![],
             gap: Range::default(),
+            growth: Growth::default(),
+        }
+    }
+}
+
+impl<T> Index<usize> for GapBuffer<T> {
+    type Output = T;
+
+    fn index(&self, index: usize) -> &T {
+        match self.get(index) {
+            Some(val) => val,
+            None => panic!(
+                "index {} out of range for GapBuffer of len {}",
+                index,
+                self.len()
+            ),
+        }
+    }
+}
+
+impl<T> IndexMut<usize> for GapBuffer<T> {
+    fn index_mut(&mut self, index: usize) -> &mut T {
+        let len = self.len();
+        match self.get_mut(index) {
+            Some(val) => val,
+            None => panic!("index {} out of range for GapBuffer of len {}", index, len),
         }
     }
 }
@@ -55,6 +120,43 @@ impl<T> GapBuffer<T> {
         Self::default()
     }
 
+    /// Creates the buffer with the gap of `capacity` elements.
+    #[instrument(name = "GapBuffer::with_capacity")]
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            buf: Vec::with_capacity(capacity),
+            gap: 0..capacity,
+            growth: Growth::default(),
+        }
+    }
+
+    #[instrument(name = "GapBuffer::growth")]
+    pub fn growth(&self) -> Growth {
+        self.growth
+    }
+
+    #[instrument(name = "GapBuffer::set_growth")]
+    pub fn set_growth(&mut self, growth: Growth) {
+        self.growth = growth;
+    }
+
+    /// Enlarges the gap to at least `additional` elements, following the
+    /// growth policy.
+    #[instrument(name = "GapBuffer::reserve")]
+    pub fn reserve(&mut self, additional: usize) {
+        if self.gap.len() < additional {
+            self.enlarge_gap(additional);
+        }
+    }
+
+    /// Shrinks the capacity to the length, e.g. removes the gap.
+    #[instrument(name = "GapBuffer::shrink_to_fit")]
+    pub fn shrink_to_fit(&mut self) {
+        if !self.gap.is_empty() {
+            self.resize(self.len());
+        }
+    }
+
     #[instrument(name = "GapBuffer::capacity")]
     pub fn capacity(&self) -> usize {
         self.buf.capacity()
@@ -72,20 +174,87 @@ impl<T> GapBuffer<T> {
 
     #[instrument(name = "GapBuffer::get")]
     pub fn get(&self, index: usize) -> Option<&T> {
-        let raw = self.index_to_raw(index);
-        if raw < self.capacity() {
-            unsafe { Some(&*self.space(raw)) }
-        } else {
-            None
+        match self.index_to_raw(index) {
+            Some(raw) if raw < self.capacity() => unsafe { Some(&*self.space(raw)) },
+            _ => None,
+        }
+    }
+
+    #[instrument(name = "GapBuffer::get_mut")]
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut T> {
+        match self.index_to_raw(index) {
+            Some(raw) if raw < self.capacity() => unsafe { Some(&mut *self.space_mut(raw)) },
+            _ => None,
+        }
+    }
+
+    /// Returns the elements before and after the gap.
+    #[instrument(name = "GapBuffer::as_slices")]
+    pub fn as_slices(&self) -> (&[T], &[T]) {
+        unsafe {
+            let before = slice::from_raw_parts(self.space(0), self.gap.start);
+            let after =
+                slice::from_raw_parts(self.space(self.gap.end), self.capacity() - self.gap.end);
+            (before, after)
+        }
+    }
+
+    /// Returns the mutable elements before and after the gap.
+    #[instrument(name = "GapBuffer::as_mut_slices")]
+    pub fn as_mut_slices(&mut self) -> (&mut [T], &mut [T]) {
+        let gap = self.gap.clone();
+        let capacity = self.capacity();
+        unsafe {
+            let before = slice::from_raw_parts_mut(self.space_mut(0), gap.start);
+            let after = slice::from_raw_parts_mut(self.space_mut(gap.end), capacity - gap.end);
+            (before, after)
         }
     }
 
+    /// Moves the gap to the end, and returns all the elements as a
+    /// single slice.
+    #[instrument(name = "GapBuffer::make_contiguous")]
+    pub fn make_contiguous(&mut self) -> &mut [T] {
+        self.set_position(self.len());
+        self.as_mut_slices().0
+    }
+
+    /// Converts into `Vec<T>`, by moving the gap to the end.
+    #[instrument(name = "GapBuffer::into_vec")]
+    pub fn into_vec(mut self) -> Vec<T> {
+        let len = self.len();
+        self.set_position(len);
+        let mut buf = mem::take(&mut self.buf);
+        self.gap = Range::default();
+        unsafe { buf.set_len(len) }
+        buf
+    }
+
+    #[instrument(name = "GapBuffer::iter")]
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter::new(self)
+    }
+
+    #[instrument(name = "GapBuffer::iter_mut")]
+    pub fn iter_mut(&mut self) -> IterMut<'_, T> {
+        IterMut::new(self)
+    }
+
+    /// Returns the cursor position, e.g. the index of the gap.
+    #[instrument(name = "GapBuffer::position")]
+    pub fn position(&self) -> usize {
+        self.gap.start
+    }
+
     #[instrument(name = "GapBuffer::insert_iter", skip(iterable))]
     pub fn insert_iter<I>(&mut self, iterable: I)
     where
         I: IntoIterator<Item = T>,
     {
-        for val in iterable {
+        let iter = iterable.into_iter();
+        // enlarges once for the known elements.
+        self.reserve(iter.size_hint().0);
+        for val in iter {
             self.insert(val)
         }
     }
@@ -93,7 +262,7 @@ impl<T> GapBuffer<T> {
     #[instrument(name = "GapBuffer::insert", skip(val))]
     pub fn insert(&mut self, val: T) {
         if self.gap.is_empty() {
-            self.enlarge_gap();
+            self.enlarge_gap(1);
         }
         unsafe {
             let index = self.gap.start;
@@ -127,12 +296,97 @@ impl<T> GapBuffer<T> {
         }
     }
 
-    #[instrument(name = "GapBuffer::enlarge_cap")]
-    fn enlarge_gap(&mut self) {
-        let mut new_capacity = self.capacity() * 2;
-        if new_capacity == 0 {
-            new_capacity = 4;
+    /// Moves the cursor forward by one.  Returns false at the end.
+    #[instrument(name = "GapBuffer::move_forward")]
+    pub fn move_forward(&mut self) -> bool {
+        if self.gap.end == self.capacity() {
+            return false;
+        }
+        unsafe {
+            copy(self.space(self.gap.end), self.space_mut(self.gap.start), 1);
+        }
+        self.gap = self.gap.start + 1..self.gap.end + 1;
+        true
+    }
+
+    /// Moves the cursor back by one.  Returns false at the beginning.
+    #[instrument(name = "GapBuffer::move_back")]
+    pub fn move_back(&mut self) -> bool {
+        if self.gap.start == 0 {
+            return false;
+        }
+        self.gap = self.gap.start - 1..self.gap.end - 1;
+        unsafe {
+            copy(self.space(self.gap.start), self.space_mut(self.gap.end), 1);
+        }
+        true
+    }
+
+    /// Removes the element right after the cursor.
+    #[instrument(name = "GapBuffer::remove")]
+    pub fn remove(&mut self) -> Option<T> {
+        if self.gap.end == self.capacity() {
+            return None;
         }
+        let val = unsafe { read(self.space(self.gap.end)) };
+        self.gap.end += 1;
+        Some(val)
+    }
+
+    /// Removes the element right before the cursor.
+    #[instrument(name = "GapBuffer::backspace")]
+    pub fn backspace(&mut self) -> Option<T> {
+        if self.gap.start == 0 {
+            return None;
+        }
+        self.gap.start -= 1;
+        unsafe { Some(read(self.space(self.gap.start))) }
+    }
+
+    /// Drops the elements in `range` and leaves the cursor at
+    /// `range.start`.
+    ///
+    /// panic if `range` is out of bounds.
+    #[instrument(name = "GapBuffer::delete_range")]
+    pub fn delete_range(&mut self, range: Range<usize>) {
+        if range.start > range.end || range.end > self.len() {
+            panic!("range {:?} out of range for GapBuffer", range);
+        }
+        self.set_position(range.start);
+        for _ in range {
+            // extend the gap first, in case drop panics.
+            self.gap.end += 1;
+            unsafe { drop_in_place(self.space_mut(self.gap.end - 1)) }
+        }
+    }
+
+    /// Replaces the elements in `range` with `iterable` and leaves the
+    /// cursor right after the inserted elements.
+    ///
+    /// panic if `range` is out of bounds.
+    #[instrument(name = "GapBuffer::replace_range", skip(iterable))]
+    pub fn replace_range<I>(&mut self, range: Range<usize>, iterable: I)
+    where
+        I: IntoIterator<Item = T>,
+    {
+        self.delete_range(range);
+        self.insert_iter(iterable);
+    }
+
+    // enlarges the gap to at least `additional` elements.
+    #[instrument(name = "GapBuffer::enlarge_gap")]
+    fn enlarge_gap(&mut self, additional: usize) {
+        let new_capacity = self
+            .growth
+            .next_capacity(self.capacity())
+            .max(self.len() + additional);
+        self.resize(new_capacity);
+    }
+
+    // moves the elements to the new buffer of `new_capacity`, which
+    // should be at least the length.
+    #[instrument(name = "GapBuffer::resize")]
+    fn resize(&mut self, new_capacity: usize) {
         let after_gap_len = self.capacity() - self.gap.end;
         let new_gap = self.gap.start..new_capacity - after_gap_len;
         let mut new = Vec::with_capacity(new_capacity);
@@ -152,11 +406,11 @@ impl<T> GapBuffer<T> {
     }
 
     #[instrument(name = "GapBuffer::index_to_raw")]
-    fn index_to_raw(&self, index: usize) -> usize {
+    fn index_to_raw(&self, index: usize) -> Option<usize> {
         if index < self.gap.start {
-            index
+            Some(index)
         } else {
-            index + self.gap.end
+            index.checked_add(self.gap.len())
         }
     }
 
@@ -175,7 +429,18 @@ impl<T> GapBuffer<T> {
 
 #[cfg(test)]
 mod tests {
-    use super::GapBuffer;
+    use super::{GapBuffer, Growth};
+    use std::rc::Rc;
+
+    fn contents(buf: &GapBuffer<char>) -> String {
+        buf.iter().collect()
+    }
+
+    fn from_str(s: &str) -> GapBuffer<char> {
+        let mut buf = GapBuffer::new();
+        buf.insert_iter(s.chars());
+        buf
+    }
 
     #[test]
     fn capacity() {
@@ -194,4 +459,282 @@ mod tests {
         let buf = GapBuffer::<char>::new();
         assert_eq!(buf.get(0), None);
     }
+
+    #[test]
+    fn remove_and_backspace() {
+        let mut buf = from_str("hello");
+        assert_eq!(buf.remove(), None);
+        assert_eq!(buf.backspace(), Some('o'));
+        buf.set_position(1);
+        assert_eq!(buf.remove(), Some('e'));
+        assert_eq!(buf.backspace(), Some('h'));
+        assert_eq!(buf.backspace(), None);
+        assert_eq!(buf.position(), 0);
+        assert_eq!(contents(&buf), "ll");
+    }
+
+    #[test]
+    fn move_cursor() {
+        let mut buf = from_str("abc");
+        assert!(!buf.move_forward());
+        assert!(buf.move_back());
+        assert!(buf.move_back());
+        assert_eq!(buf.position(), 1);
+        buf.insert('x');
+        assert!(buf.move_forward());
+        assert!(buf.move_forward());
+        assert!(!buf.move_forward());
+        assert_eq!(buf.position(), 4);
+        assert_eq!(contents(&buf), "axbc");
+        while buf.move_back() {}
+        assert_eq!(buf.position(), 0);
+        assert_eq!(contents(&buf), "axbc");
+    }
+
+    #[test]
+    fn get_mut() {
+        let mut buf = from_str("abc");
+        *buf.get_mut(1).unwrap() = 'B';
+        assert_eq!(buf.get_mut(3), None);
+        assert_eq!(contents(&buf), "aBc");
+    }
+
+    #[test]
+    fn delete_and_replace_range() {
+        let mut buf = from_str("hello, world");
+        buf.delete_range(5..7);
+        assert_eq!(buf.position(), 5);
+        assert_eq!(contents(&buf), "helloworld");
+        buf.replace_range(0..5, "goodbye ".chars());
+        assert_eq!(buf.position(), 8);
+        assert_eq!(contents(&buf), "goodbye world");
+        buf.delete_range(13..13);
+        assert_eq!(buf.position(), 13);
+        buf.replace_range(0..13, None);
+        assert_eq!(buf.len(), 0);
+    }
+
+    #[test]
+    #[should_panic]
+    fn delete_range_out_of_bounds() {
+        let mut buf = from_str("abc");
+        buf.delete_range(2..4);
+    }
+
+    #[test]
+    fn drop_removed() {
+        let rc = Rc::new(());
+        let mut buf = GapBuffer::new();
+        buf.insert_iter((0..10).map(|_| rc.clone()));
+        assert_eq!(Rc::strong_count(&rc), 11);
+        drop(buf.remove());
+        drop(buf.backspace());
+        assert_eq!(Rc::strong_count(&rc), 10);
+        buf.delete_range(2..5);
+        assert_eq!(Rc::strong_count(&rc), 7);
+        buf.replace_range(0..2, [rc.clone()]);
+        assert_eq!(Rc::strong_count(&rc), 6);
+        buf.move_back();
+        buf.move_forward();
+        assert_eq!(Rc::strong_count(&rc), 6);
+        drop(buf);
+        assert_eq!(Rc::strong_count(&rc), 1);
+    }
+
+    #[test]
+    fn index_after_gap() {
+        let mut buf = from_str("Load of the Rings");
+        buf.set_position(12);
+        buf.insert_iter("Onion ".chars());
+        assert_eq!(buf[12], 'O');
+        assert_eq!(buf[18], 'R');
+        assert_eq!(buf[22], 's');
+        assert_eq!(buf.get(23), None);
+        assert_eq!(buf.get(usize::MAX), None);
+        assert_eq!(buf.get_mut(usize::MAX), None);
+        buf[18] = 'r';
+        assert_eq!(contents(&buf), "Load of the Onion rings");
+    }
+
+    #[test]
+    #[should_panic(expected = "index 3 out of range for GapBuffer of len 3")]
+    fn index_out_of_bounds() {
+        let buf = from_str("abc");
+        let _ = buf[3];
+    }
+
+    #[test]
+    #[should_panic(expected = "index 3 out of range for GapBuffer of len 3")]
+    fn index_mut_out_of_bounds() {
+        let mut buf = from_str("abc");
+        buf[3] = 'd';
+    }
+
+    #[test]
+    fn slices() {
+        let mut buf = from_str("hello world");
+        buf.set_position(5);
+        let (before, after) = buf.as_slices();
+        assert_eq!(before, ['h', 'e', 'l', 'l', 'o']);
+        assert_eq!(after, [' ', 'w', 'o', 'r', 'l', 'd']);
+        buf.as_mut_slices().1[1] = 'W';
+        let all = buf.make_contiguous();
+        assert_eq!(all.iter().collect::<String>(), "hello World");
+        all.reverse();
+        assert_eq!(buf.position(), 11);
+        assert_eq!(contents(&buf), "dlroW olleh");
+    }
+
+    #[test]
+    fn iterators() {
+        let mut buf = from_str("abcdef");
+        buf.set_position(2);
+        assert_eq!(buf.iter().len(), 6);
+        assert_eq!(buf.iter().rev().collect::<String>(), "fedcba");
+        for c in buf.iter_mut() {
+            *c = c.to_ascii_uppercase();
+        }
+        for c in &mut buf {
+            c.make_ascii_uppercase();
+        }
+        assert_eq!((&buf).into_iter().collect::<String>(), "ABCDEF");
+        let mut iter = buf.into_iter();
+        assert_eq!(iter.len(), 6);
+        assert_eq!(iter.next(), Some('A'));
+        assert_eq!(iter.next_back(), Some('F'));
+        assert_eq!(iter.collect::<String>(), "BCDE");
+    }
+
+    #[test]
+    fn into_iter_drop() {
+        let rc = Rc::new(());
+        let mut buf = GapBuffer::new();
+        buf.insert_iter((0..10).map(|_| rc.clone()));
+        buf.set_position(4);
+        let mut iter = buf.into_iter();
+        iter.next();
+        iter.next_back();
+        assert_eq!(Rc::strong_count(&rc), 9);
+        drop(iter);
+        assert_eq!(Rc::strong_count(&rc), 1);
+    }
+
+    // xorshift, not to depend on rand.
+    fn rng(state: &mut u32) -> usize {
+        *state ^= *state << 13;
+        *state ^= *state >> 17;
+        *state ^= *state << 5;
+        *state as usize
+    }
+
+    #[test]
+    fn model() {
+        let mut state = 0x2545_f491;
+        let mut buf = GapBuffer::new();
+        let mut model: Vec<usize> = vec![];
+        let mut pos = 0;
+        for i in 0..10_000 {
+            match rng(&mut state) % 6 {
+                0 | 1 => {
+                    buf.insert(i);
+                    model.insert(pos, i);
+                    pos += 1;
+                }
+                2 => {
+                    pos = rng(&mut state) % (model.len() + 1);
+                    buf.set_position(pos);
+                }
+                3 if pos < model.len() => {
+                    assert_eq!(buf.remove(), Some(model.remove(pos)));
+                }
+                4 if pos > 0 => {
+                    pos -= 1;
+                    assert_eq!(buf.backspace(), Some(model.remove(pos)));
+                }
+                5 if !model.is_empty() => {
+                    let index = rng(&mut state) % model.len();
+                    buf[index] += 1;
+                    model[index] += 1;
+                }
+                _ => {}
+            }
+            assert_eq!(buf.len(), model.len());
+            assert_eq!(buf.position(), pos);
+            if i % 100 == 0 {
+                for (index, val) in model.iter().enumerate() {
+                    assert_eq!(buf[index], *val);
+                }
+                assert_eq!(buf.get(model.len()), None);
+                assert!(buf.iter().eq(model.iter()));
+                assert!(buf.iter().rev().eq(model.iter().rev()));
+                let (before, after) = buf.as_slices();
+                assert_eq!([before, after].concat(), model);
+            }
+        }
+        assert_eq!(buf.into_iter().collect::<Vec<_>>(), model);
+    }
+
+    #[test]
+    fn with_capacity() {
+        let mut buf = GapBuffer::with_capacity(10);
+        assert_eq!(buf.capacity(), 10);
+        buf.insert_iter("0123456789".chars());
+        assert_eq!(buf.capacity(), 10);
+        buf.insert('a');
+        assert_eq!(buf.capacity(), 20);
+        assert_eq!(contents(&buf), "0123456789a");
+    }
+
+    #[test]
+    fn reserve_and_shrink() {
+        let mut buf = from_str("hello world");
+        buf.set_position(5);
+        buf.reserve(100);
+        assert!(buf.capacity() >= 111);
+        let capacity = buf.capacity();
+        buf.reserve(100);
+        assert_eq!(buf.capacity(), capacity);
+        assert_eq!(contents(&buf), "hello world");
+        buf.shrink_to_fit();
+        assert_eq!(buf.capacity(), 11);
+        assert_eq!(buf.position(), 5);
+        assert_eq!(contents(&buf), "hello world");
+        buf.insert(',');
+        assert_eq!(contents(&buf), "hello, world");
+
+        let mut buf = GapBuffer::<char>::with_capacity(8);
+        buf.shrink_to_fit();
+        assert_eq!(buf.capacity(), 0);
+        buf.insert('a');
+        assert_eq!(contents(&buf), "a");
+    }
+
+    #[test]
+    fn growth() {
+        let mut buf = GapBuffer::new();
+        assert_eq!(buf.growth(), Growth::Double);
+        buf.set_growth(Growth::Linear(3));
+        let mut capacities = vec![];
+        for c in "abcdefg".chars() {
+            buf.insert(c);
+            capacities.push(buf.capacity());
+        }
+        assert_eq!(capacities, [3, 3, 3, 6, 6, 6, 9]);
+        buf.set_growth(Growth::Double);
+        buf.insert_iter("hi".chars());
+        buf.insert('j');
+        assert_eq!(buf.capacity(), 18);
+        assert_eq!(contents(&buf), "abcdefghij");
+    }
+
+    #[test]
+    fn insert_iter_size_hint() {
+        let mut buf = GapBuffer::new();
+        buf.insert_iter(0..100);
+        assert_eq!(buf.capacity(), 100);
+        // the exact size is unknown after filter().
+        buf.insert_iter((0..10).filter(|i| i % 2 == 0));
+        assert_eq!(buf.capacity(), 200);
+        assert_eq!(buf.len(), 105);
+    }
 }
@@ -0,0 +1,554 @@
+//! Rope, a B-tree of chunks, as an alternative to [`GapBuffer`] for
+//! the large documents.
+//!
+//! [`GapBuffer::set_position`] copies the elements between the cursor
+//! and the new position, while [`Rope`] inserts and removes anywhere in
+//! O(log n), by keeping the elements in the leaf chunks and the lengths
+//! of the subtrees in the internal nodes.
+//!
+//! [`GapBuffer`]: crate::GapBuffer
+//! [`GapBuffer::set_position`]: crate::GapBuffer::set_position
+use super::EditBuffer;
+use std::fmt::{self, Debug};
+use std::iter::FusedIterator;
+use std::ops::Range;
+use std::slice;
+use tracing::instrument;
+
+const MAX_LEAF: usize = 256;
+const MIN_LEAF: usize = MAX_LEAF / 4;
+const MAX_CHILDREN: usize = 16;
+const MIN_CHILDREN: usize = MAX_CHILDREN / 4;
+
+pub struct Rope<T> {
+    root: Node<T>,
+}
+
+enum Node<T> {
+    Leaf(Vec<T>),
+    Internal { len: usize, children: Vec<Node<T>> },
+}
+
+impl<T> Default for Rope<T> {
+    fn default() -> Self {
+        Self {
+            root: Node::Leaf(vec![]),
+        }
+    }
+}
+
+impl<T> Debug for Rope<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Rope")
+            .field("len", &self.len())
+            .field("height", &self.root.height())
+            .finish()
+    }
+}
+
+impl<T> FromIterator<T> for Rope<T> {
+    /// Builds the tree bottom up, with the half full nodes.
+    fn from_iter<I: IntoIterator<Item = T>>(iterable: I) -> Self {
+        let mut iter = iterable.into_iter().peekable();
+        let mut nodes = vec![];
+        while iter.peek().is_some() {
+            nodes.push(Node::Leaf(iter.by_ref().take(MAX_LEAF / 2).collect()));
+        }
+        while nodes.len() > 1 {
+            let mut parents = vec![];
+            let mut iter = nodes.into_iter().peekable();
+            while iter.peek().is_some() {
+                let children: Vec<_> = iter.by_ref().take(MAX_CHILDREN / 2).collect();
+                parents.push(Node::internal(children));
+            }
+            nodes = parents;
+        }
+        Self {
+            root: nodes.pop().unwrap_or(Node::Leaf(vec![])),
+        }
+    }
+}
+
+impl<T> Node<T> {
+    fn internal(children: Vec<Node<T>>) -> Self {
+        let len = children.iter().map(Node::len).sum();
+        Self::Internal { len, children }
+    }
+
+    fn len(&self) -> usize {
+        match self {
+            Self::Leaf(vals) => vals.len(),
+            Self::Internal { len, .. } => *len,
+        }
+    }
+
+    fn height(&self) -> usize {
+        match self {
+            Self::Leaf(_) => 0,
+            Self::Internal { children, .. } => children[0].height() + 1,
+        }
+    }
+
+    fn is_underfull(&self) -> bool {
+        match self {
+            Self::Leaf(vals) => vals.len() < MIN_LEAF,
+            Self::Internal { children, .. } => children.len() < MIN_CHILDREN,
+        }
+    }
+
+    fn is_overfull(&self) -> bool {
+        match self {
+            Self::Leaf(vals) => vals.len() > MAX_LEAF,
+            Self::Internal { children, .. } => children.len() > MAX_CHILDREN,
+        }
+    }
+
+    fn get(&self, mut index: usize) -> &T {
+        let mut node = self;
+        loop {
+            match node {
+                Self::Leaf(vals) => return &vals[index],
+                Self::Internal { children, .. } => {
+                    let (i, offset) = Self::child_at(children, index);
+                    node = &children[i];
+                    index = offset;
+                }
+            }
+        }
+    }
+
+    // child containing `index`, with the index in the child.  The index
+    // right after the last element belongs to the last child.
+    fn child_at(children: &[Node<T>], mut index: usize) -> (usize, usize) {
+        let last = children.len() - 1;
+        for (i, child) in children[..last].iter().enumerate() {
+            if index < child.len() {
+                return (i, index);
+            }
+            index -= child.len();
+        }
+        (last, index)
+    }
+
+    // returns the new right sibling on overflow.
+    fn insert(&mut self, index: usize, val: T) -> Option<Self> {
+        match self {
+            Self::Leaf(vals) => vals.insert(index, val),
+            Self::Internal { len, children } => {
+                let (i, offset) = Self::child_at(children, index);
+                *len += 1;
+                if let Some(sibling) = children[i].insert(offset, val) {
+                    children.insert(i + 1, sibling);
+                }
+            }
+        }
+        if self.is_overfull() {
+            Some(self.split())
+        } else {
+            None
+        }
+    }
+
+    fn remove(&mut self, index: usize) -> T {
+        match self {
+            Self::Leaf(vals) => vals.remove(index),
+            Self::Internal { len, children } => {
+                let (i, offset) = Self::child_at(children, index);
+                *len -= 1;
+                let val = children[i].remove(offset);
+                if children[i].is_underfull() && children.len() > 1 {
+                    Self::rebalance(children, i);
+                }
+                val
+            }
+        }
+    }
+
+    // removes the subtrees inside `range` as a whole, and drains the
+    // leaves at the both ends.  The node is not emptied.
+    fn delete_range(&mut self, range: Range<usize>) {
+        match self {
+            Self::Leaf(vals) => drop(vals.drain(range)),
+            Self::Internal { len, children } => {
+                *len -= range.len();
+                let mut start = 0;
+                children.retain_mut(|child| {
+                    let child_range = start..start + child.len();
+                    start = child_range.end;
+                    if range.start <= child_range.start && child_range.end <= range.end {
+                        return false;
+                    }
+                    if range.start < child_range.end && child_range.start < range.end {
+                        let from = range.start.max(child_range.start) - child_range.start;
+                        let to = range.end.min(child_range.end) - child_range.start;
+                        child.delete_range(from..to);
+                    }
+                    true
+                });
+                let mut i = 0;
+                while i < children.len() && children.len() > 1 {
+                    if children[i].is_underfull() {
+                        Self::rebalance(children, i);
+                        i = i.saturating_sub(1);
+                    } else {
+                        i += 1;
+                    }
+                }
+            }
+        }
+    }
+
+    // merges the underfull child `i` with the sibling, and splits it
+    // again if it's too large.
+    fn rebalance(children: &mut Vec<Node<T>>, i: usize) {
+        let left = if i + 1 < children.len() { i } else { i - 1 };
+        let right = children.remove(left + 1);
+        children[left].merge(right);
+        if children[left].is_overfull() {
+            let sibling = children[left].split();
+            children.insert(left + 1, sibling);
+        }
+    }
+
+    // moves the second half out as the new right sibling.
+    fn split(&mut self) -> Self {
+        match self {
+            Self::Leaf(vals) => Self::Leaf(vals.split_off(vals.len() / 2)),
+            Self::Internal { len, children } => {
+                let right = Self::internal(children.split_off(children.len() / 2));
+                *len -= right.len();
+                right
+            }
+        }
+    }
+
+    // both nodes are at the same height.
+    fn merge(&mut self, other: Self) {
+        match (self, other) {
+            (Self::Leaf(vals), Self::Leaf(mut other)) => vals.append(&mut other),
+            (
+                Self::Internal { len, children },
+                Self::Internal {
+                    len: other_len,
+                    children: mut other,
+                },
+            ) => {
+                *len += other_len;
+                children.append(&mut other);
+            }
+            _ => unreachable!("merging the nodes at the different heights"),
+        }
+    }
+}
+
+impl<T> Rope<T> {
+    #[instrument(name = "Rope::new")]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[instrument(name = "Rope::len")]
+    pub fn len(&self) -> usize {
+        self.root.len()
+    }
+
+    #[instrument(name = "Rope::is_empty")]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    #[instrument(name = "Rope::get")]
+    pub fn get(&self, index: usize) -> Option<&T> {
+        if index < self.len() {
+            Some(self.root.get(index))
+        } else {
+            None
+        }
+    }
+
+    /// panic if `index` is out of bounds.
+    #[instrument(name = "Rope::insert", skip(val))]
+    pub fn insert(&mut self, index: usize, val: T) {
+        if index > self.len() {
+            panic!("index {} out of range for Rope", index);
+        }
+        if let Some(sibling) = self.root.insert(index, val) {
+            let left = std::mem::replace(&mut self.root, Node::Leaf(vec![]));
+            self.root = Node::internal(vec![left, sibling]);
+        }
+    }
+
+    /// panic if `index` is out of bounds.
+    #[instrument(name = "Rope::remove")]
+    pub fn remove(&mut self, index: usize) -> T {
+        if index >= self.len() {
+            panic!("index {} out of range for Rope", index);
+        }
+        let val = self.root.remove(index);
+        self.shrink();
+        val
+    }
+
+    /// panic if `range` is out of bounds.
+    #[instrument(name = "Rope::delete_range")]
+    pub fn delete_range(&mut self, range: Range<usize>) {
+        if range.start > range.end || range.end > self.len() {
+            panic!("range {:?} out of range for Rope", range);
+        }
+        if range.len() == self.len() {
+            self.root = Node::Leaf(vec![]);
+            return;
+        }
+        self.root.delete_range(range);
+        self.shrink();
+    }
+
+    // shrinks the tree from the root.
+    fn shrink(&mut self) {
+        while let Node::Internal { children, .. } = &mut self.root {
+            if children.len() > 1 {
+                break;
+            }
+            self.root = children.pop().unwrap();
+        }
+    }
+
+    #[instrument(name = "Rope::iter")]
+    pub fn iter(&self) -> Iter<'_, T> {
+        let mut iter = Iter {
+            stack: vec![],
+            leaf: [].iter(),
+            len: self.len(),
+        };
+        iter.descend(&self.root);
+        iter
+    }
+}
+
+impl<T> EditBuffer<T> for Rope<T> {
+    fn len(&self) -> usize {
+        Rope::len(self)
+    }
+
+    fn get(&self, index: usize) -> Option<&T> {
+        Rope::get(self, index)
+    }
+
+    fn insert_at(&mut self, index: usize, val: T) {
+        self.insert(index, val)
+    }
+
+    fn remove_at(&mut self, index: usize) -> Option<T> {
+        if index < self.len() {
+            Some(self.remove(index))
+        } else {
+            None
+        }
+    }
+
+    fn delete_range(&mut self, range: Range<usize>) {
+        Rope::delete_range(self, range)
+    }
+}
+
+/// In-order iterator over the leaves.
+pub struct Iter<'a, T> {
+    stack: Vec<slice::Iter<'a, Node<T>>>,
+    leaf: slice::Iter<'a, T>,
+    len: usize,
+}
+
+impl<'a, T> Iter<'a, T> {
+    // goes down to the leftmost leaf of `node`.
+    fn descend(&mut self, mut node: &'a Node<T>) {
+        loop {
+            match node {
+                Node::Leaf(vals) => {
+                    self.leaf = vals.iter();
+                    return;
+                }
+                Node::Internal { children, .. } => {
+                    let mut iter = children.iter();
+                    node = iter.next().unwrap();
+                    self.stack.push(iter);
+                }
+            }
+        }
+    }
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(val) = self.leaf.next() {
+                self.len -= 1;
+                return Some(val);
+            }
+            let next = self.stack.last_mut()?.next();
+            match next {
+                Some(node) => self.descend(node),
+                None => {
+                    self.stack.pop();
+                }
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+impl<T> ExactSizeIterator for Iter<'_, T> {}
+impl<T> FusedIterator for Iter<'_, T> {}
+
+impl<'a, T> IntoIterator for &'a Rope<T> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Node, Rope, MAX_LEAF};
+    use crate::{EditBuffer, GapBuffer};
+
+    // checks the cached lengths and that all the leaves are at the same
+    // height, returning the length.
+    fn check<T>(node: &Node<T>, height: usize) -> usize {
+        match node {
+            Node::Leaf(vals) => {
+                assert_eq!(height, 0);
+                vals.len()
+            }
+            Node::Internal { len, children } => {
+                assert!(height > 0);
+                let sum = children.iter().map(|child| check(child, height - 1)).sum();
+                assert_eq!(*len, sum);
+                sum
+            }
+        }
+    }
+
+    fn rng(state: &mut u32) -> usize {
+        *state ^= *state << 13;
+        *state ^= *state >> 17;
+        *state ^= *state << 5;
+        *state as usize
+    }
+
+    #[test]
+    fn empty() {
+        let mut rope = Rope::<u8>::new();
+        assert!(rope.is_empty());
+        assert_eq!(rope.get(0), None);
+        assert_eq!(rope.remove_at(0), None);
+        assert_eq!(rope.iter().next(), None);
+    }
+
+    #[test]
+    fn from_iter() {
+        for len in [0, 1, MAX_LEAF, 100_000] {
+            let rope: Rope<usize> = (0..len).collect();
+            assert_eq!(check(&rope.root, rope.root.height()), len);
+            assert_eq!(rope.iter().len(), len);
+            assert!(rope.iter().copied().eq(0..len));
+            assert_eq!(rope.get(len / 2), (len > 0).then_some(&(len / 2)));
+            assert_eq!(rope.get(len), None);
+        }
+    }
+
+    #[test]
+    fn grow_and_shrink() {
+        let mut rope = Rope::new();
+        for i in 0..50_000 {
+            rope.insert(i, i);
+        }
+        assert!(rope.root.height() > 1);
+        check(&rope.root, rope.root.height());
+        for i in 0..50_000 {
+            assert_eq!(rope.remove(0), i);
+        }
+        assert_eq!(rope.root.height(), 0);
+        assert!(rope.is_empty());
+    }
+
+    #[test]
+    fn delete_range() {
+        let len = 100_000;
+        for (start, end) in [(0, 0), (10, 20), (1000, 99_000), (0, 99_999), (1, len)] {
+            let mut rope: Rope<usize> = (0..len).collect();
+            rope.delete_range(start..end);
+            check(&rope.root, rope.root.height());
+            assert!(rope.iter().copied().eq((0..start).chain(end..len)));
+        }
+        let mut rope: Rope<usize> = (0..len).collect();
+        rope.delete_range(0..len);
+        assert!(rope.is_empty());
+        assert_eq!(rope.root.height(), 0);
+    }
+
+    #[test]
+    #[should_panic]
+    fn insert_out_of_bounds() {
+        let mut rope = Rope::new();
+        rope.insert(1, 'a');
+    }
+
+    // the same random edits over the both EditBuffer, against Vec.
+    fn model<B: EditBuffer<usize>>(mut buf: B) -> B {
+        let mut state = 0x2545_f491;
+        let mut model = vec![];
+        for i in 0..20_000 {
+            let len = model.len();
+            match rng(&mut state) % 5 {
+                0..=2 => {
+                    let index = rng(&mut state) % (len + 1);
+                    buf.insert_at(index, i);
+                    model.insert(index, i);
+                }
+                3 => {
+                    let index = rng(&mut state) % (len + 1);
+                    assert_eq!(
+                        buf.remove_at(index),
+                        (index < len).then(|| model.remove(index))
+                    );
+                }
+                _ => {
+                    let start = rng(&mut state) % (len + 1);
+                    let end = start + rng(&mut state) % (len - start + 1).min(300);
+                    buf.delete_range(start..end);
+                    model.drain(start..end);
+                    let index = rng(&mut state) % (model.len() + 1);
+                    buf.insert_iter_at(index, i..i + 100);
+                    model.splice(index..index, i..i + 100);
+                }
+            }
+            assert_eq!(buf.len(), model.len());
+            let index = rng(&mut state) % (model.len() + 1);
+            assert_eq!(buf.get(index), model.get(index));
+        }
+        for (index, val) in model.iter().enumerate() {
+            assert_eq!(buf.get(index), Some(val));
+        }
+        buf
+    }
+
+    #[test]
+    fn rope_model() {
+        let rope = model(Rope::new());
+        check(&rope.root, rope.root.height());
+        let vals: Vec<_> = rope.iter().collect();
+        assert_eq!(vals.len(), rope.len());
+    }
+
+    #[test]
+    fn gap_buffer_model() {
+        model(GapBuffer::new());
+    }
+}
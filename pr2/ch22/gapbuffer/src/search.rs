@@ -0,0 +1,239 @@
+//! Searching the [`GapBuffer`] in place, without moving the gap.
+//!
+//! It's the Boyer-Moore-Horspool algorithm over the logical indexes, so
+//! that the matches can span the gap.
+use super::GapBuffer;
+use std::collections::HashMap;
+use std::hash::Hash;
+use tracing::instrument;
+
+// bad character shifts of the pattern.
+struct Horspool<'p, T> {
+    pattern: &'p [T],
+    shifts: HashMap<&'p T, usize>,
+}
+
+impl<'p, T: Eq + Hash> Horspool<'p, T> {
+    // shifts for the forward search, by the last element of the window.
+    fn forward(pattern: &'p [T]) -> Self {
+        let last = pattern.len().saturating_sub(1);
+        let shifts = pattern[..last]
+            .iter()
+            .enumerate()
+            .map(|(i, val)| (val, last - i))
+            .collect();
+        Self { pattern, shifts }
+    }
+
+    // shifts for the backward search, by the first element of the window.
+    fn backward(pattern: &'p [T]) -> Self {
+        let shifts = pattern
+            .iter()
+            .enumerate()
+            .skip(1)
+            .rev()
+            .map(|(i, val)| (val, i))
+            .collect();
+        Self { pattern, shifts }
+    }
+
+    fn shift(&self, val: &T) -> usize {
+        self.shifts.get(val).copied().unwrap_or(self.pattern.len())
+    }
+
+    fn matches_at(&self, buf: &GapBuffer<T>, pos: usize) -> bool {
+        self.pattern
+            .iter()
+            .enumerate()
+            .rev()
+            .all(|(i, val)| buf[pos + i] == *val)
+    }
+
+    // the first match at or after `from`.
+    fn find(&self, buf: &GapBuffer<T>, from: usize) -> Option<usize> {
+        let m = self.pattern.len();
+        let mut pos = from;
+        while pos + m <= buf.len() {
+            if self.matches_at(buf, pos) {
+                return Some(pos);
+            }
+            pos += self.shift(&buf[pos + m - 1]);
+        }
+        None
+    }
+
+    // the last match ending at or before `to`.
+    fn rfind(&self, buf: &GapBuffer<T>, to: usize) -> Option<usize> {
+        let m = self.pattern.len();
+        let mut pos = to.checked_sub(m)?;
+        loop {
+            if self.matches_at(buf, pos) {
+                return Some(pos);
+            }
+            pos = pos.checked_sub(self.shift(&buf[pos]))?;
+        }
+    }
+}
+
+/// Iterator over the non-overlapping matches, returned by
+/// [`GapBuffer::find_iter`].
+pub struct Matches<'a, 'p, T> {
+    buf: &'a GapBuffer<T>,
+    horspool: Horspool<'p, T>,
+    // start of the next search, or past the end when done.
+    from: usize,
+}
+
+impl<T: Eq + Hash> Iterator for Matches<'_, '_, T> {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        if self.from > self.buf.len() {
+            return None;
+        }
+        match self.horspool.find(self.buf, self.from) {
+            Some(pos) => {
+                // the empty pattern matches at every position.
+                self.from = pos + self.horspool.pattern.len().max(1);
+                Some(pos)
+            }
+            None => {
+                self.from = usize::MAX;
+                None
+            }
+        }
+    }
+}
+
+impl<T: Eq + Hash> GapBuffer<T> {
+    /// Returns the index of the first match of `pattern`.
+    #[instrument(name = "GapBuffer::find", skip(pattern))]
+    pub fn find(&self, pattern: &[T]) -> Option<usize> {
+        Horspool::forward(pattern).find(self, 0)
+    }
+
+    /// Returns the index of the last match of `pattern`.
+    #[instrument(name = "GapBuffer::rfind", skip(pattern))]
+    pub fn rfind(&self, pattern: &[T]) -> Option<usize> {
+        Horspool::backward(pattern).rfind(self, self.len())
+    }
+
+    /// Returns the indexes of the non-overlapping matches of `pattern`,
+    /// from the start.
+    #[instrument(name = "GapBuffer::find_iter", skip(pattern))]
+    pub fn find_iter<'a, 'p>(&'a self, pattern: &'p [T]) -> Matches<'a, 'p, T> {
+        Matches {
+            buf: self,
+            horspool: Horspool::forward(pattern),
+            from: 0,
+        }
+    }
+
+    /// Replaces all the non-overlapping matches of `pattern` with
+    /// `replacement`, and returns the number of the replaced matches.
+    /// The cursor is left right after the first replacement.
+    #[instrument(name = "GapBuffer::replace_all", skip(pattern, replacement))]
+    pub fn replace_all(&mut self, pattern: &[T], replacement: &[T]) -> usize
+    where
+        T: Clone,
+    {
+        let matches: Vec<_> = self.find_iter(pattern).collect();
+        // from the last one, not to shift the remaining matches.
+        for pos in matches.iter().rev() {
+            self.replace_range(*pos..pos + pattern.len(), replacement.iter().cloned());
+        }
+        matches.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::GapBuffer;
+
+    // with the gap at `pos`.
+    fn from_str(s: &str, pos: usize) -> GapBuffer<char> {
+        let mut buf = GapBuffer::new();
+        buf.insert_iter(s.chars());
+        buf.set_position(pos);
+        buf
+    }
+
+    fn chars(s: &str) -> Vec<char> {
+        s.chars().collect()
+    }
+
+    #[test]
+    fn find_across_gap() {
+        let text = "the quick brown fox jumps over the lazy dog";
+        for pos in 0..=text.len() {
+            let buf = from_str(text, pos);
+            assert_eq!(buf.find(&chars("the")), Some(0), "{pos}");
+            assert_eq!(buf.rfind(&chars("the")), Some(31), "{pos}");
+            assert_eq!(buf.find(&chars("fox jumps")), Some(16), "{pos}");
+            assert_eq!(buf.rfind(&chars("fox jumps")), Some(16), "{pos}");
+            assert_eq!(buf.find(&chars("dog")), Some(40), "{pos}");
+            assert_eq!(buf.find(&chars("cat")), None, "{pos}");
+            assert_eq!(buf.rfind(&chars("cat")), None, "{pos}");
+            assert_eq!(buf.find(&chars(text)), Some(0), "{pos}");
+            assert_eq!(buf.rfind(&chars(text)), Some(0), "{pos}");
+        }
+    }
+
+    #[test]
+    fn find_empty() {
+        let buf = from_str("abc", 1);
+        assert_eq!(buf.find(&[]), Some(0));
+        assert_eq!(buf.rfind(&[]), Some(3));
+        assert_eq!(buf.find_iter(&[]).collect::<Vec<_>>(), [0, 1, 2, 3]);
+        assert_eq!(buf.find(&chars("abcd")), None);
+        let empty = GapBuffer::<char>::new();
+        assert_eq!(empty.find(&['a']), None);
+        assert_eq!(empty.rfind(&['a']), None);
+    }
+
+    #[test]
+    fn find_iter() {
+        let buf = from_str("aaaaa", 2);
+        assert_eq!(buf.find_iter(&chars("aa")).collect::<Vec<_>>(), [0, 2]);
+        let buf = from_str("日本語と日本", 5);
+        assert_eq!(buf.find_iter(&chars("日本")).collect::<Vec<_>>(), [0, 4]);
+    }
+
+    #[test]
+    fn replace_all() {
+        let mut buf = from_str("foo bar foo baz foo", 9);
+        assert_eq!(buf.replace_all(&chars("foo"), &chars("quux")), 3);
+        assert_eq!(buf.iter().collect::<String>(), "quux bar quux baz quux");
+        assert_eq!(buf.position(), 4);
+        assert_eq!(buf.replace_all(&chars("quux "), &[]), 2);
+        assert_eq!(buf.iter().collect::<String>(), "bar baz quux");
+        assert_eq!(buf.replace_all(&chars("x"), &chars("xx")), 1);
+        assert_eq!(buf.iter().collect::<String>(), "bar baz quuxx");
+        assert_eq!(buf.replace_all(&chars("none"), &chars("!")), 0);
+    }
+
+    #[test]
+    fn model() {
+        let mut state: u32 = 0x2545_f491;
+        let mut rng = move || {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            state as usize
+        };
+        for _ in 0..200 {
+            let text: String = (0..rng() % 200)
+                .map(|_| ['a', 'b', 'c'][rng() % 3])
+                .collect();
+            let pattern: String = (0..1 + rng() % 4)
+                .map(|_| ['a', 'b', 'c'][rng() % 3])
+                .collect();
+            let buf = from_str(&text, rng() % (text.len() + 1));
+            let p = chars(&pattern);
+            assert_eq!(buf.find(&p), text.find(&pattern), "{text} {pattern}");
+            assert_eq!(buf.rfind(&p), text.rfind(&pattern), "{text} {pattern}");
+            let expected: Vec<_> = text.match_indices(&pattern).map(|(i, _)| i).collect();
+            assert_eq!(buf.find_iter(&p).collect::<Vec<_>>(), expected);
+        }
+    }
+}
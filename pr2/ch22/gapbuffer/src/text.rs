@@ -0,0 +1,282 @@
+//! Text buffer of the editor, on top of [`GapBuffer<char>`].
+//!
+//! It keeps the char index of each line start, updated incrementally
+//! on every edit, to map between the char index and the line/column.
+use super::GapBuffer;
+use std::fmt::{self, Debug, Display, Write};
+use std::ops::Range;
+use tracing::instrument;
+
+pub struct TextBuffer {
+    chars: GapBuffer<char>,
+    // char index of each line start, e.g. always starts with 0.
+    lines: Vec<usize>,
+}
+
+impl Default for TextBuffer {
+    fn default() -> Self {
+        Self {
+            chars: GapBuffer::new(),
+            lines: vec![0],
+        }
+    }
+}
+
+impl Debug for TextBuffer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TextBuffer")
+            .field("chars", &self.chars)
+            .field("lines.len", &self.lines.len())
+            .finish()
+    }
+}
+
+impl Display for TextBuffer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.chars.iter().try_for_each(|c| f.write_char(*c))
+    }
+}
+
+impl From<&str> for TextBuffer {
+    fn from(s: &str) -> Self {
+        let mut text = Self::new();
+        text.insert_str(0, s);
+        text
+    }
+}
+
+impl TextBuffer {
+    #[instrument(name = "TextBuffer::new")]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the length in chars.
+    #[instrument(name = "TextBuffer::len")]
+    pub fn len(&self) -> usize {
+        self.chars.len()
+    }
+
+    #[instrument(name = "TextBuffer::is_empty")]
+    pub fn is_empty(&self) -> bool {
+        self.chars.is_empty()
+    }
+
+    /// Returns the number of lines, e.g. the number of `'\n'` plus one.
+    #[instrument(name = "TextBuffer::line_count")]
+    pub fn line_count(&self) -> usize {
+        self.lines.len()
+    }
+
+    #[instrument(name = "TextBuffer::get")]
+    pub fn get(&self, index: usize) -> Option<char> {
+        self.chars.get(index).copied()
+    }
+
+    /// Returns the line `n`, without the trailing `'\n'`.
+    #[instrument(name = "TextBuffer::line")]
+    pub fn line(&self, n: usize) -> Option<String> {
+        let range = self.line_range(n)?;
+        Some(range.map(|i| self.chars[i]).collect())
+    }
+
+    /// Returns the zero-based line and column, in chars, of `index`.
+    #[instrument(name = "TextBuffer::char_to_line_col")]
+    pub fn char_to_line_col(&self, index: usize) -> Option<(usize, usize)> {
+        if index > self.len() {
+            return None;
+        }
+        let line = self.line_of(index);
+        Some((line, index - self.lines[line]))
+    }
+
+    /// Returns the char index of the zero-based line and column.  The
+    /// column can point right after the last char of the line.
+    #[instrument(name = "TextBuffer::line_col_to_char")]
+    pub fn line_col_to_char(&self, line: usize, col: usize) -> Option<usize> {
+        let range = self.line_range(line)?;
+        if col > range.len() {
+            return None;
+        }
+        Some(range.start + col)
+    }
+
+    #[instrument(name = "TextBuffer::insert")]
+    pub fn insert(&mut self, index: usize, c: char) {
+        self.insert_str(index, c.encode_utf8(&mut [0; 4]));
+    }
+
+    /// panic if `index` is out of bounds.
+    #[instrument(name = "TextBuffer::insert_str")]
+    pub fn insert_str(&mut self, index: usize, s: &str) {
+        self.chars.set_position(index);
+        let line = self.line_of(index);
+        let mut new_lines = vec![];
+        for c in s.chars() {
+            self.chars.insert(c);
+            if c == '\n' {
+                new_lines.push(self.chars.position());
+            }
+        }
+        let n = self.chars.position() - index;
+        for start in &mut self.lines[line + 1..] {
+            *start += n;
+        }
+        self.lines.splice(line + 1..line + 1, new_lines);
+    }
+
+    /// Deletes the chars in `range`.
+    ///
+    /// panic if `range` is out of bounds.
+    #[instrument(name = "TextBuffer::delete")]
+    pub fn delete(&mut self, range: Range<usize>) {
+        self.chars.delete_range(range.clone());
+        // line starts in (range.start, range.end] are gone.
+        let first = self.line_of(range.start) + 1;
+        let last = self.lines.partition_point(|&start| start <= range.end);
+        self.lines.drain(first..last);
+        for start in &mut self.lines[first..] {
+            *start -= range.len();
+        }
+    }
+
+    // index of the line containing the char `index`.
+    fn line_of(&self, index: usize) -> usize {
+        self.lines.partition_point(|&start| start <= index) - 1
+    }
+
+    // char range of the line `n`, without the trailing '\n'.
+    fn line_range(&self, n: usize) -> Option<Range<usize>> {
+        let start = *self.lines.get(n)?;
+        let end = match self.lines.get(n + 1) {
+            Some(next) => next - 1,
+            None => self.len(),
+        };
+        Some(start..end)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TextBuffer;
+
+    const TEXT: &str = "héllo\n日本語のテキスト\n\n🦀 crab";
+
+    // line starts computed from scratch.
+    fn check(text: &TextBuffer) {
+        let s = text.to_string();
+        let lines: Vec<_> = s.split('\n').collect();
+        assert_eq!(text.line_count(), lines.len());
+        let mut index = 0;
+        for (n, line) in lines.iter().enumerate() {
+            assert_eq!(text.line(n).as_deref(), Some(*line));
+            assert_eq!(text.line_col_to_char(n, 0), Some(index));
+            assert_eq!(text.char_to_line_col(index), Some((n, 0)));
+            index += line.chars().count() + 1;
+        }
+        assert_eq!(text.line(lines.len()), None);
+    }
+
+    #[test]
+    fn from_str() {
+        let text = TextBuffer::from(TEXT);
+        assert_eq!(text.to_string(), TEXT);
+        assert_eq!(text.len(), TEXT.chars().count());
+        assert_eq!(text.line_count(), 4);
+        assert_eq!(text.line(1).unwrap(), "日本語のテキスト");
+        assert_eq!(text.line(2).unwrap(), "");
+        assert_eq!(text.line(3).unwrap(), "🦀 crab");
+        check(&text);
+    }
+
+    #[test]
+    fn empty() {
+        let text = TextBuffer::new();
+        assert!(text.is_empty());
+        assert_eq!(text.line_count(), 1);
+        assert_eq!(text.line(0).unwrap(), "");
+        assert_eq!(text.char_to_line_col(0), Some((0, 0)));
+        assert_eq!(text.char_to_line_col(1), None);
+        assert_eq!(text.line_col_to_char(0, 0), Some(0));
+        assert_eq!(text.line_col_to_char(0, 1), None);
+    }
+
+    #[test]
+    fn line_col() {
+        let text = TextBuffer::from(TEXT);
+        assert_eq!(text.char_to_line_col(1), Some((0, 1)));
+        assert_eq!(text.char_to_line_col(5), Some((0, 5)));
+        assert_eq!(text.char_to_line_col(6), Some((1, 0)));
+        assert_eq!(text.char_to_line_col(9), Some((1, 3)));
+        assert_eq!(text.get(9), Some('の'));
+        assert_eq!(text.char_to_line_col(15), Some((2, 0)));
+        assert_eq!(text.char_to_line_col(16), Some((3, 0)));
+        assert_eq!(text.get(16), Some('🦀'));
+        assert_eq!(text.char_to_line_col(text.len()), Some((3, 6)));
+        assert_eq!(text.char_to_line_col(text.len() + 1), None);
+        assert_eq!(text.line_col_to_char(1, 3), Some(9));
+        assert_eq!(text.line_col_to_char(1, 8), Some(14));
+        assert_eq!(text.line_col_to_char(1, 9), None);
+        assert_eq!(text.line_col_to_char(3, 6), Some(text.len()));
+        assert_eq!(text.line_col_to_char(4, 0), None);
+    }
+
+    #[test]
+    fn edit() {
+        let mut text = TextBuffer::from(TEXT);
+        text.insert_str(3, "—\nñ\n");
+        assert_eq!(text.line(0).unwrap(), "hél—");
+        assert_eq!(text.line(1).unwrap(), "ñ");
+        assert_eq!(text.line(2).unwrap(), "lo");
+        assert_eq!(text.line_count(), 6);
+        check(&text);
+        text.insert(0, '\n');
+        check(&text);
+        text.insert(text.len(), '🦀');
+        assert_eq!(text.line(6).unwrap(), "🦀 crab🦀");
+        check(&text);
+        // joins the lines.
+        text.delete(5..8);
+        assert_eq!(text.line(1).unwrap(), "hél—lo");
+        check(&text);
+        text.delete(0..text.len());
+        assert_eq!(text.to_string(), "");
+        assert_eq!(text.line_count(), 1);
+        check(&text);
+    }
+
+    #[test]
+    fn random_edits() {
+        const CHARS: [char; 6] = ['a', 'é', '\n', '語', '🦀', '\n'];
+        let mut state: u32 = 0x2545_f491;
+        let mut rng = move || {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            state as usize
+        };
+        let mut text = TextBuffer::new();
+        let mut model = String::new();
+        for _ in 0..500 {
+            let len = text.len();
+            if rng() % 3 == 0 && len > 0 {
+                let start = rng() % len;
+                let end = start + rng() % (len - start).min(5);
+                text.delete(start..end);
+                let chars: Vec<char> = model.chars().collect();
+                model = chars[..start].iter().chain(&chars[end..]).collect();
+            } else {
+                let index = rng() % (len + 1);
+                let s: String = (0..rng() % 4).map(|_| CHARS[rng() % CHARS.len()]).collect();
+                text.insert_str(index, &s);
+                let byte = model
+                    .char_indices()
+                    .nth(index)
+                    .map_or(model.len(), |(i, _)| i);
+                model.insert_str(byte, &s);
+            }
+            assert_eq!(text.to_string(), model);
+            check(&text);
+        }
+    }
+}
@@ -0,0 +1,12 @@
+#!/bin/sh
+# Runs the refwithflag tests, e.g. the tagged.rs and atomic.rs ones, under
+# Miri with the strict provenance, to check the pointer tagging.
+#
+# Miri is installed by
+#
+#   rustup +nightly component add miri
+set -eu
+
+cd "$(dirname "$0")"
+MIRIFLAGS="${MIRIFLAGS:-} -Zmiri-strict-provenance" \
+	exec cargo +nightly miri test -p refwithflag "$@"
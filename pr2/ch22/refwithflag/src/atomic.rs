@@ -0,0 +1,179 @@
+//! Atomic tagged pointer, e.g. the pointer with the mark bit for the
+//! lock-free algorithms.
+//!
+//! The (pointer, tag) pair is updated atomically as a single
+//! [`AtomicPtr`], with the tag in the low `BITS` bits.
+use std::fmt::{self, Debug};
+use std::mem::align_of;
+use std::ptr;
+use std::sync::atomic::{AtomicPtr, Ordering};
+
+pub struct AtomicTaggedPtr<T, const BITS: usize = 1> {
+    ptr: AtomicPtr<T>,
+}
+
+impl<T, const BITS: usize> Default for AtomicTaggedPtr<T, BITS> {
+    fn default() -> Self {
+        Self::null()
+    }
+}
+
+impl<T, const BITS: usize> Debug for AtomicTaggedPtr<T, BITS> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (ptr, tag) = self.load(Ordering::Relaxed);
+        f.debug_struct("AtomicTaggedPtr")
+            .field("ptr", &ptr)
+            .field("tag", &tag)
+            .finish()
+    }
+}
+
+impl<T, const BITS: usize> AtomicTaggedPtr<T, BITS> {
+    const MASK: usize = (1 << BITS) - 1;
+    const CHECK: () = assert!(
+        BITS <= align_of::<T>().trailing_zeros() as usize,
+        "BITS exceeds log2(align_of::<T>())",
+    );
+
+    /// panic if `tag` doesn't fit in `BITS` bits.
+    pub fn new(ptr: *mut T, tag: usize) -> Self {
+        Self {
+            ptr: AtomicPtr::new(Self::pack(ptr, tag)),
+        }
+    }
+
+    pub fn null() -> Self {
+        Self::new(ptr::null_mut(), 0)
+    }
+
+    pub fn into_inner(self) -> (*mut T, usize) {
+        Self::unpack(self.ptr.into_inner())
+    }
+
+    pub fn load(&self, order: Ordering) -> (*mut T, usize) {
+        Self::unpack(self.ptr.load(order))
+    }
+
+    /// panic if `tag` doesn't fit in `BITS` bits.
+    pub fn store(&self, ptr: *mut T, tag: usize, order: Ordering) {
+        self.ptr.store(Self::pack(ptr, tag), order);
+    }
+
+    /// Stores `new` if the current pair is `current`, and returns the
+    /// previous pair, as [`AtomicPtr::compare_exchange`].
+    ///
+    /// panic if the tag doesn't fit in `BITS` bits.
+    pub fn compare_exchange(
+        &self,
+        current: (*mut T, usize),
+        new: (*mut T, usize),
+        success: Ordering,
+        failure: Ordering,
+    ) -> Result<(*mut T, usize), (*mut T, usize)> {
+        self.ptr
+            .compare_exchange(
+                Self::pack(current.0, current.1),
+                Self::pack(new.0, new.1),
+                success,
+                failure,
+            )
+            .map(Self::unpack)
+            .map_err(Self::unpack)
+    }
+
+    /// Sets the `tag` bits, keeping the pointer, and returns the previous
+    /// pair.
+    ///
+    /// panic if `tag` doesn't fit in `BITS` bits.
+    pub fn fetch_or_tag(&self, tag: usize, order: Ordering) -> (*mut T, usize) {
+        assert!(tag <= Self::MASK, "tag {} exceeds {} bits", tag, BITS);
+        Self::unpack(self.ptr.fetch_or(tag, order))
+    }
+
+    fn pack(ptr: *mut T, tag: usize) -> *mut T {
+        #[allow(clippy::let_unit_value)]
+        let () = Self::CHECK;
+        assert!(tag <= Self::MASK, "tag {} exceeds {} bits", tag, BITS);
+        ptr.map_addr(|addr| addr | tag)
+    }
+
+    fn unpack(ptr: *mut T) -> (*mut T, usize) {
+        (
+            ptr.map_addr(|addr| addr & !Self::MASK),
+            ptr.addr() & Self::MASK,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::AtomicTaggedPtr;
+    use std::ptr;
+    use std::sync::atomic::Ordering::{Relaxed, SeqCst};
+    use std::thread;
+
+    #[test]
+    fn load_and_store() {
+        let mut x = 1u64;
+        let mut y = 2u64;
+        let (px, py) = (&mut x as *mut u64, &mut y as *mut u64);
+        let a = AtomicTaggedPtr::<_, 3>::new(px, 5);
+        assert_eq!(a.load(SeqCst), (px, 5));
+        a.store(py, 2, SeqCst);
+        assert_eq!(a.load(SeqCst), (py, 2));
+        assert_eq!(unsafe { *a.load(SeqCst).0 }, 2);
+        assert_eq!(a.into_inner(), (py, 2));
+        let null = AtomicTaggedPtr::<u64>::default();
+        assert_eq!(null.load(Relaxed), (ptr::null_mut(), 0));
+    }
+
+    #[test]
+    fn compare_exchange() {
+        let mut x = 1u32;
+        let px = &mut x as *mut u32;
+        let a = AtomicTaggedPtr::<_, 2>::new(px, 0);
+        assert_eq!(
+            a.compare_exchange((px, 1), (px, 3), SeqCst, SeqCst),
+            Err((px, 0))
+        );
+        assert_eq!(
+            a.compare_exchange((px, 0), (px, 3), SeqCst, SeqCst),
+            Ok((px, 0))
+        );
+        assert_eq!(
+            a.compare_exchange((px, 3), (ptr::null_mut(), 1), SeqCst, SeqCst),
+            Ok((px, 3))
+        );
+        assert_eq!(a.load(SeqCst), (ptr::null_mut(), 1));
+    }
+
+    #[test]
+    fn fetch_or_tag() {
+        let mut x = 1u32;
+        let px = &mut x as *mut u32;
+        let a = AtomicTaggedPtr::<_, 2>::new(px, 1);
+        assert_eq!(a.fetch_or_tag(2, SeqCst), (px, 1));
+        assert_eq!(a.fetch_or_tag(1, SeqCst), (px, 3));
+        assert_eq!(a.load(SeqCst), (px, 3));
+    }
+
+    #[test]
+    #[should_panic]
+    fn tag_too_large() {
+        AtomicTaggedPtr::<u16>::new(ptr::null_mut(), 2);
+    }
+
+    // only one thread wins the mark.
+    #[test]
+    fn mark_once() {
+        let mut x = 0u64;
+        let a = AtomicTaggedPtr::<u64>::new(&mut x, 0);
+        let winners: usize = thread::scope(|s| {
+            let handles: Vec<_> = (0..8)
+                .map(|_| s.spawn(|| usize::from(a.fetch_or_tag(1, SeqCst).1 == 0)))
+                .collect();
+            handles.into_iter().map(|h| h.join().unwrap()).sum()
+        });
+        assert_eq!(winners, 1);
+    }
+}
@@ -0,0 +1,296 @@
+//! Harris lock-free sorted list, the sample of [`AtomicTaggedPtr`].
+//!
+//! The node is deleted logically first, by marking its `next` pointer,
+//! and then physically, by unlinking it from the predecessor.  Any
+//! thread finding the marked node unlinks it on the way.
+//!
+//! The unlinked nodes are retired, not freed, as the other threads may
+//! still be reading them.  They're freed when the list is dropped.
+use super::AtomicTaggedPtr;
+use std::sync::atomic::Ordering::{AcqRel, Acquire, Relaxed, Release};
+use std::sync::Mutex;
+
+const MARKED: usize = 1;
+
+pub struct List<T> {
+    head: AtomicTaggedPtr<Node<T>>,
+    retired: Mutex<Vec<Retired<T>>>,
+}
+
+struct Node<T> {
+    key: T,
+    next: AtomicTaggedPtr<Node<T>>,
+}
+
+// unlinked node, owned by the list.
+struct Retired<T>(*mut Node<T>);
+
+// the nodes are shared by the threads, as in Arc<T>.
+unsafe impl<T: Send + Sync> Send for List<T> {}
+unsafe impl<T: Send + Sync> Sync for List<T> {}
+
+impl<T> Default for List<T> {
+    fn default() -> Self {
+        Self {
+            head: AtomicTaggedPtr::null(),
+            retired: Mutex::new(vec![]),
+        }
+    }
+}
+
+impl<T> Drop for List<T> {
+    fn drop(&mut self) {
+        let (mut curr, _) = self.head.load(Relaxed);
+        while !curr.is_null() {
+            let node = unsafe { Box::from_raw(curr) };
+            curr = node.next.load(Relaxed).0;
+        }
+        let retired = self.retired.get_mut().unwrap_or_else(|e| e.into_inner());
+        for Retired(node) in retired.drain(..) {
+            drop(unsafe { Box::from_raw(node) });
+        }
+    }
+}
+
+impl<T: Ord> List<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns false if `key` is already in the list.
+    pub fn insert(&self, key: T) -> bool {
+        let node = Box::into_raw(Box::new(Node {
+            key,
+            next: AtomicTaggedPtr::null(),
+        }));
+        loop {
+            let (prev, curr) = self.search(unsafe { &(*node).key });
+            if !curr.is_null() && unsafe { (*curr).key == (*node).key } {
+                // never published.
+                drop(unsafe { Box::from_raw(node) });
+                return false;
+            }
+            unsafe { (*node).next.store(curr, 0, Relaxed) };
+            if prev
+                .compare_exchange((curr, 0), (node, 0), Release, Relaxed)
+                .is_ok()
+            {
+                return true;
+            }
+        }
+    }
+
+    /// Returns false if `key` is not in the list.
+    pub fn remove(&self, key: &T) -> bool {
+        loop {
+            let (prev, curr) = self.search(key);
+            if curr.is_null() || unsafe { (*curr).key != *key } {
+                return false;
+            }
+            // logical deletion, by the single winner.
+            let (next, tag) = unsafe { (*curr).next.fetch_or_tag(MARKED, AcqRel) };
+            if tag == MARKED {
+                // deleted by the other thread, which search() unlinks.
+                continue;
+            }
+            // physical deletion, or leave it to the next search().
+            match prev.compare_exchange((curr, 0), (next, 0), AcqRel, Acquire) {
+                Ok(_) => self.retire(curr),
+                Err(_) => {
+                    self.search(key);
+                }
+            }
+            return true;
+        }
+    }
+
+    pub fn contains(&self, key: &T) -> bool {
+        let (mut curr, _) = self.head.load(Acquire);
+        while let Some(node) = unsafe { curr.as_ref() } {
+            let (next, tag) = node.next.load(Acquire);
+            if node.key >= *key {
+                return node.key == *key && tag != MARKED;
+            }
+            curr = next;
+        }
+        false
+    }
+
+    /// Returns the keys not deleted, in order.
+    pub fn to_vec(&self) -> Vec<T>
+    where
+        T: Clone,
+    {
+        let mut keys = vec![];
+        let (mut curr, _) = self.head.load(Acquire);
+        while let Some(node) = unsafe { curr.as_ref() } {
+            let (next, tag) = node.next.load(Acquire);
+            if tag != MARKED {
+                keys.push(node.key.clone());
+            }
+            curr = next;
+        }
+        keys
+    }
+
+    // returns the unmarked link to the first node not less than `key`,
+    // unlinking the marked nodes on the way.
+    fn search(&self, key: &T) -> (&AtomicTaggedPtr<Node<T>>, *mut Node<T>) {
+        'retry: loop {
+            let mut prev = &self.head;
+            let (mut curr, _) = prev.load(Acquire);
+            loop {
+                let node = match unsafe { curr.as_ref() } {
+                    None => return (prev, curr),
+                    Some(node) => node,
+                };
+                let (next, tag) = node.next.load(Acquire);
+                if tag == MARKED {
+                    match prev.compare_exchange((curr, 0), (next, 0), AcqRel, Acquire) {
+                        Ok(_) => {
+                            self.retire(curr);
+                            curr = next;
+                            continue;
+                        }
+                        // prev is marked or changed.
+                        Err(_) => continue 'retry,
+                    }
+                }
+                if node.key >= *key {
+                    return (prev, curr);
+                }
+                prev = &node.next;
+                curr = next;
+            }
+        }
+    }
+
+    fn retire(&self, node: *mut Node<T>) {
+        let mut retired = self.retired.lock().unwrap_or_else(|e| e.into_inner());
+        retired.push(Retired(node));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::List;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+
+    #[test]
+    fn sorted() {
+        let list = List::new();
+        for key in [5, 1, 4, 2, 3] {
+            assert!(list.insert(key));
+        }
+        assert!(!list.insert(3));
+        assert_eq!(list.to_vec(), [1, 2, 3, 4, 5]);
+        assert!(list.contains(&4));
+        assert!(list.remove(&4));
+        assert!(!list.remove(&4));
+        assert!(!list.contains(&4));
+        assert!(!list.contains(&6));
+        assert!(list.remove(&1));
+        assert!(list.remove(&5));
+        assert_eq!(list.to_vec(), [2, 3]);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn concurrent_insert() {
+        let list = List::new();
+        thread::scope(|s| {
+            for t in 0..4 {
+                let list = &list;
+                s.spawn(move || {
+                    for key in 0..1000 {
+                        list.insert(key * 4 + t);
+                    }
+                });
+            }
+        });
+        assert_eq!(list.to_vec(), (0..4000).collect::<Vec<_>>());
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn concurrent_insert_and_remove() {
+        let list = List::new();
+        for key in 0..2000 {
+            list.insert(key);
+        }
+        thread::scope(|s| {
+            // removes the even keys, while inserting the new ones.
+            for t in 0..2 {
+                let list = &list;
+                s.spawn(move || {
+                    for key in (t * 1000..(t + 1) * 1000).filter(|key| key % 2 == 0) {
+                        assert!(list.remove(&key));
+                    }
+                });
+            }
+            for t in 0..2 {
+                let list = &list;
+                s.spawn(move || {
+                    for key in 2000 + t * 1000..2000 + (t + 1) * 1000 {
+                        assert!(list.insert(key));
+                    }
+                });
+            }
+        });
+        let expected: Vec<_> = (0..2000)
+            .filter(|key| key % 2 == 1)
+            .chain(2000..4000)
+            .collect();
+        assert_eq!(list.to_vec(), expected);
+    }
+
+    // the same keys by all the threads, only one wins each time.
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn concurrent_contention() {
+        let list = List::new();
+        let inserted = AtomicUsize::new(0);
+        let removed = AtomicUsize::new(0);
+        thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    for round in 0..500 {
+                        let key = round % 16;
+                        if list.insert(key) {
+                            inserted.fetch_add(1, Ordering::Relaxed);
+                        }
+                        if list.remove(&key) {
+                            removed.fetch_add(1, Ordering::Relaxed);
+                        }
+                    }
+                });
+            }
+        });
+        let remaining = list.to_vec().len();
+        assert_eq!(
+            inserted.load(Ordering::Relaxed),
+            removed.load(Ordering::Relaxed) + remaining
+        );
+    }
+
+    #[test]
+    fn drop_keys() {
+        let rc = Arc::new(());
+        // Arc<()> are all equal, to compare by the index.
+        let list = List::new();
+        for i in 0..10 {
+            list.insert((i, rc.clone()));
+        }
+        // the duplicate is dropped right away.
+        list.insert((3, rc.clone()));
+        assert_eq!(Arc::strong_count(&rc), 11);
+        for i in 0..5 {
+            list.remove(&(i, rc.clone()));
+        }
+        assert_eq!(Arc::strong_count(&rc), 11);
+        drop(list);
+        assert_eq!(Arc::strong_count(&rc), 1);
+    }
+}
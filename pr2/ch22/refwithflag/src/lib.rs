@@ -3,16 +3,43 @@
 //! *const T, a shared raw pointer, example demonstrated in
 //! [Programming Rust, 2nd Edition], page 642.
 //!
+//! The pointer tagging is checked by Miri with the strict provenance
+//! through `miri.sh`.
+//!
 //! [programming rust, 2nd edition]: https://www.oreilly.com/library/view/programming-rust-2nd/9781492052586/
 use std::fmt::{self, Debug};
+use std::hash::{Hash, Hasher};
 use std::marker::PhantomData;
 use std::mem::align_of;
+use std::num::NonZeroUsize;
+use std::ops::Deref;
+use std::ptr::NonNull;
+
+mod atomic;
+pub mod harris;
+mod tagged;
+
+pub use atomic::AtomicTaggedPtr;
+pub use tagged::{TaggedBox, TaggedMut, TaggedRef};
 
+/// Shared reference with the bool flag in the low bit.
+///
+/// The pointer keeps its provenance, as the flag is set and cleared with
+/// [`NonNull::map_addr`] instead of the round trip through `usize`.  Run
+/// the tests under Miri in the strict provenance mode with:
+///
+/// ```sh
+/// MIRIFLAGS=-Zmiri-strict-provenance cargo +nightly miri test -p refwithflag
+/// ```
 pub struct RefWithFlag<'a, T> {
-    ptr_and_bit: usize,
+    ptr_and_bit: NonNull<T>,
     behaves_like: PhantomData<&'a T>,
 }
 
+// the same auto traits as &T.
+unsafe impl<T: Sync> Send for RefWithFlag<'_, T> {}
+unsafe impl<T: Sync> Sync for RefWithFlag<'_, T> {}
+
 impl<'a, T> Debug for RefWithFlag<'a, T> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("RefWithFlag")
@@ -21,20 +48,49 @@ impl<'a, T> Debug for RefWithFlag<'a, T> {
     }
 }
 
+impl<'a, T> Clone for RefWithFlag<'a, T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<'a, T> Copy for RefWithFlag<'a, T> {}
+
+/// Compares the referenced values and the flags.
+impl<'a, T: PartialEq> PartialEq for RefWithFlag<'a, T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_ref() == other.as_ref() && self.as_flag() == other.as_flag()
+    }
+}
+
+impl<'a, T: Eq> Eq for RefWithFlag<'a, T> {}
+
+impl<'a, T: Hash> Hash for RefWithFlag<'a, T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.as_ref().hash(state);
+        self.as_flag().hash(state);
+    }
+}
+
 impl<'a, T> AsRef<T> for RefWithFlag<'a, T> {
     fn as_ref(&self) -> &T {
-        unsafe {
-            let ptr = (self.ptr_and_bit & !1) as *const T;
-            &*ptr
-        }
+        unsafe { self.ptr().as_ref() }
+    }
+}
+
+impl<'a, T> Deref for RefWithFlag<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.as_ref()
     }
 }
 
 impl<'a, T> From<(&'a T, bool)> for RefWithFlag<'a, T> {
     fn from(from: (&'a T, bool)) -> Self {
-        assert!(align_of::<T>() % 2 == 0);
+        assert!(align_of::<T>().is_multiple_of(2));
         Self {
-            ptr_and_bit: from.0 as *const T as usize | from.1 as usize,
+            ptr_and_bit: NonNull::from(from.0).map_addr(|addr| addr | from.1 as usize),
             behaves_like: PhantomData,
         }
     }
@@ -42,7 +98,18 @@ impl<'a, T> From<(&'a T, bool)> for RefWithFlag<'a, T> {
 
 impl<'a, T> RefWithFlag<'a, T> {
     pub fn as_flag(&self) -> bool {
-        self.ptr_and_bit & 1 == 1
+        self.ptr_and_bit.addr().get() & 1 == 1
+    }
+
+    /// Returns the reference with the original lifetime, and the flag.
+    pub fn into_parts(self) -> (&'a T, bool) {
+        unsafe { (self.ptr().as_ref(), self.as_flag()) }
+    }
+
+    fn ptr(&self) -> NonNull<T> {
+        // the address is not zero, as the reference is aligned to 2.
+        self.ptr_and_bit
+            .map_addr(|addr| NonZeroUsize::new(addr.get() & !1).unwrap())
     }
 }
 
@@ -70,4 +137,40 @@ mod tests {
         assert!(flagged.as_flag());
         assert!(!unflagged.as_flag());
     }
+
+    #[test]
+    fn deref_and_into_parts() {
+        let v = [1u16, 2, 3];
+        let flagged = RefWithFlag::from((&v[1], true));
+        assert_eq!(*flagged, 2);
+        let (r, flag) = flagged.into_parts();
+        assert!(flag);
+        assert!(std::ptr::eq(r, &v[1]));
+        assert_eq!(*r, 2);
+    }
+
+    #[test]
+    fn copy_eq_and_hash() {
+        use std::collections::HashSet;
+
+        let (a, b) = (String::from("a"), String::from("a"));
+        let x = RefWithFlag::from((&a, true));
+        let y = x;
+        assert_eq!(x, y);
+        assert_eq!(x, RefWithFlag::from((&b, true)));
+        assert_ne!(x, RefWithFlag::from((&a, false)));
+        let set: HashSet<_> = [x, y, RefWithFlag::from((&b, false))].into();
+        assert_eq!(set.len(), 2);
+    }
+
+    #[test]
+    fn send_sync() {
+        fn send_sync<T: Send + Sync>(_: T) {}
+        let v = vec![1, 2];
+        let flagged = RefWithFlag::from((&v, true));
+        std::thread::scope(|s| {
+            s.spawn(|| assert_eq!(flagged.len(), 2));
+        });
+        send_sync(flagged);
+    }
 }
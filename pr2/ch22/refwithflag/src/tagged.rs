@@ -0,0 +1,363 @@
+//! Tagged pointers, generalising [`RefWithFlag`] to multiple bits.
+//!
+//! The tag is packed into the low `BITS` bits of the pointer, which are
+//! always zero thanks to the alignment of `T`.  `BITS` is checked against
+//! `align_of::<T>()` at compile time.
+//!
+//! [`RefWithFlag`]: crate::RefWithFlag
+use std::fmt::{self, Debug};
+use std::marker::PhantomData;
+use std::mem::align_of;
+use std::ops::{Deref, DerefMut};
+use std::ptr::NonNull;
+
+// the pointer with the tag, shared by all the variants.
+struct Tagged<T, const BITS: usize> {
+    ptr: NonNull<T>,
+}
+
+impl<T, const BITS: usize> Clone for Tagged<T, BITS> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T, const BITS: usize> Copy for Tagged<T, BITS> {}
+
+impl<T, const BITS: usize> Tagged<T, BITS> {
+    const MASK: usize = (1 << BITS) - 1;
+    const CHECK: () = assert!(
+        BITS <= align_of::<T>().trailing_zeros() as usize,
+        "BITS exceeds log2(align_of::<T>())",
+    );
+
+    fn new(ptr: NonNull<T>, tag: usize) -> Self {
+        #[allow(clippy::let_unit_value)]
+        let () = Self::CHECK;
+        assert!(tag <= Self::MASK, "tag {} exceeds {} bits", tag, BITS);
+        Self {
+            ptr: ptr.map_addr(|addr| addr | tag),
+        }
+    }
+
+    fn ptr(self) -> NonNull<T> {
+        // the address is not zero, as the tag is added to the non-null.
+        unsafe { NonNull::new_unchecked(self.ptr.as_ptr().map_addr(|addr| addr & !Self::MASK)) }
+    }
+
+    fn tag(self) -> usize {
+        self.ptr.as_ptr().addr() & Self::MASK
+    }
+
+    fn set_tag(&mut self, tag: usize) {
+        *self = Self::new(self.ptr(), tag);
+    }
+}
+
+/// Shared reference with the `BITS` bits of tag.
+///
+/// Too many bits for the alignment fails to compile:
+///
+/// ```compile_fail
+/// use refwithflag::TaggedRef;
+///
+/// let x = 1u16;
+/// let _ = TaggedRef::<_, 2>::new(&x, 0);
+/// ```
+pub struct TaggedRef<'a, T, const BITS: usize> {
+    tagged: Tagged<T, BITS>,
+    behaves_like: PhantomData<&'a T>,
+}
+
+/// Mutable reference with the `BITS` bits of tag.
+pub struct TaggedMut<'a, T, const BITS: usize> {
+    tagged: Tagged<T, BITS>,
+    behaves_like: PhantomData<&'a mut T>,
+}
+
+/// Owned [`Box`] with the `BITS` bits of tag.
+pub struct TaggedBox<T, const BITS: usize> {
+    tagged: Tagged<T, BITS>,
+    behaves_like: PhantomData<Box<T>>,
+}
+
+// the same auto traits as the references and the box.
+unsafe impl<T: Sync, const BITS: usize> Send for TaggedRef<'_, T, BITS> {}
+unsafe impl<T: Sync, const BITS: usize> Sync for TaggedRef<'_, T, BITS> {}
+unsafe impl<T: Send, const BITS: usize> Send for TaggedMut<'_, T, BITS> {}
+unsafe impl<T: Sync, const BITS: usize> Sync for TaggedMut<'_, T, BITS> {}
+unsafe impl<T: Send, const BITS: usize> Send for TaggedBox<T, BITS> {}
+unsafe impl<T: Sync, const BITS: usize> Sync for TaggedBox<T, BITS> {}
+
+impl<'a, T, const BITS: usize> TaggedRef<'a, T, BITS> {
+    /// panic if `tag` doesn't fit in `BITS` bits.
+    pub fn new(r: &'a T, tag: usize) -> Self {
+        Self {
+            tagged: Tagged::new(NonNull::from(r), tag),
+            behaves_like: PhantomData,
+        }
+    }
+
+    /// Returns the reference with the original lifetime.
+    pub fn get(self) -> &'a T {
+        unsafe { self.tagged.ptr().as_ref() }
+    }
+
+    pub fn tag(&self) -> usize {
+        self.tagged.tag()
+    }
+
+    /// panic if `tag` doesn't fit in `BITS` bits.
+    pub fn set_tag(&mut self, tag: usize) {
+        self.tagged.set_tag(tag);
+    }
+
+    /// panic if `tag` doesn't fit in `BITS` bits.
+    pub fn with_tag(mut self, tag: usize) -> Self {
+        self.set_tag(tag);
+        self
+    }
+}
+
+impl<T, const BITS: usize> Clone for TaggedRef<'_, T, BITS> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T, const BITS: usize> Copy for TaggedRef<'_, T, BITS> {}
+
+impl<'a, T, const BITS: usize> From<(&'a T, usize)> for TaggedRef<'a, T, BITS> {
+    fn from(from: (&'a T, usize)) -> Self {
+        Self::new(from.0, from.1)
+    }
+}
+
+impl<T, const BITS: usize> Deref for TaggedRef<'_, T, BITS> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { self.tagged.ptr().as_ref() }
+    }
+}
+
+impl<T: Debug, const BITS: usize> Debug for TaggedRef<'_, T, BITS> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TaggedRef")
+            .field("ref", &**self)
+            .field("tag", &self.tag())
+            .finish()
+    }
+}
+
+impl<'a, T, const BITS: usize> TaggedMut<'a, T, BITS> {
+    /// panic if `tag` doesn't fit in `BITS` bits.
+    pub fn new(r: &'a mut T, tag: usize) -> Self {
+        Self {
+            tagged: Tagged::new(NonNull::from(r), tag),
+            behaves_like: PhantomData,
+        }
+    }
+
+    /// Returns the reference with the original lifetime.
+    pub fn into_mut(self) -> &'a mut T {
+        unsafe { self.tagged.ptr().as_mut() }
+    }
+
+    pub fn tag(&self) -> usize {
+        self.tagged.tag()
+    }
+
+    /// panic if `tag` doesn't fit in `BITS` bits.
+    pub fn set_tag(&mut self, tag: usize) {
+        self.tagged.set_tag(tag);
+    }
+
+    /// panic if `tag` doesn't fit in `BITS` bits.
+    pub fn with_tag(mut self, tag: usize) -> Self {
+        self.set_tag(tag);
+        self
+    }
+}
+
+impl<'a, T, const BITS: usize> From<(&'a mut T, usize)> for TaggedMut<'a, T, BITS> {
+    fn from(from: (&'a mut T, usize)) -> Self {
+        Self::new(from.0, from.1)
+    }
+}
+
+impl<T, const BITS: usize> Deref for TaggedMut<'_, T, BITS> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { self.tagged.ptr().as_ref() }
+    }
+}
+
+impl<T, const BITS: usize> DerefMut for TaggedMut<'_, T, BITS> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { self.tagged.ptr().as_mut() }
+    }
+}
+
+impl<T: Debug, const BITS: usize> Debug for TaggedMut<'_, T, BITS> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TaggedMut")
+            .field("ref", &**self)
+            .field("tag", &self.tag())
+            .finish()
+    }
+}
+
+impl<T, const BITS: usize> TaggedBox<T, BITS> {
+    /// panic if `tag` doesn't fit in `BITS` bits.
+    pub fn new(b: Box<T>, tag: usize) -> Self {
+        let ptr = unsafe { NonNull::new_unchecked(Box::into_raw(b)) };
+        Self {
+            tagged: Tagged::new(ptr, tag),
+            behaves_like: PhantomData,
+        }
+    }
+
+    pub fn into_box(self) -> Box<T> {
+        let ptr = self.tagged.ptr();
+        std::mem::forget(self);
+        unsafe { Box::from_raw(ptr.as_ptr()) }
+    }
+
+    pub fn tag(&self) -> usize {
+        self.tagged.tag()
+    }
+
+    /// panic if `tag` doesn't fit in `BITS` bits.
+    pub fn set_tag(&mut self, tag: usize) {
+        self.tagged.set_tag(tag);
+    }
+
+    /// panic if `tag` doesn't fit in `BITS` bits.
+    pub fn with_tag(mut self, tag: usize) -> Self {
+        self.set_tag(tag);
+        self
+    }
+}
+
+impl<T, const BITS: usize> Drop for TaggedBox<T, BITS> {
+    fn drop(&mut self) {
+        unsafe { drop(Box::from_raw(self.tagged.ptr().as_ptr())) }
+    }
+}
+
+impl<T: Clone, const BITS: usize> Clone for TaggedBox<T, BITS> {
+    fn clone(&self) -> Self {
+        Self::new(Box::new((**self).clone()), self.tag())
+    }
+}
+
+impl<T, const BITS: usize> From<(Box<T>, usize)> for TaggedBox<T, BITS> {
+    fn from(from: (Box<T>, usize)) -> Self {
+        Self::new(from.0, from.1)
+    }
+}
+
+impl<T, const BITS: usize> Deref for TaggedBox<T, BITS> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { self.tagged.ptr().as_ref() }
+    }
+}
+
+impl<T, const BITS: usize> DerefMut for TaggedBox<T, BITS> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { self.tagged.ptr().as_mut() }
+    }
+}
+
+impl<T: Debug, const BITS: usize> Debug for TaggedBox<T, BITS> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TaggedBox")
+            .field("box", &**self)
+            .field("tag", &self.tag())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{TaggedBox, TaggedMut, TaggedRef};
+    use std::rc::Rc;
+
+    #[test]
+    fn tagged_ref() {
+        let v = [11u64, 22, 33];
+        let r = TaggedRef::<_, 3>::new(&v[1], 5);
+        assert_eq!(*r, 22);
+        assert_eq!(r.tag(), 5);
+        let r2 = r.with_tag(7);
+        assert_eq!(r.tag(), 5);
+        assert_eq!(r2.tag(), 7);
+        assert_eq!(*r2.get(), 22);
+        let mut r3: TaggedRef<'_, _, 3> = (&v[2], 0).into();
+        r3.set_tag(2);
+        assert_eq!((*r3, r3.tag()), (33, 2));
+    }
+
+    #[test]
+    fn zero_bits() {
+        let x = 1u8;
+        let r = TaggedRef::<_, 0>::new(&x, 0);
+        assert_eq!((*r, r.tag()), (1, 0));
+    }
+
+    #[test]
+    #[should_panic]
+    fn tag_too_large() {
+        let x = 1u32;
+        TaggedRef::<_, 2>::new(&x, 4);
+    }
+
+    #[test]
+    fn tagged_mut() {
+        let mut v = vec![1u32, 2];
+        let mut m = TaggedMut::<_, 2>::new(&mut v, 3);
+        m.push(3);
+        m.set_tag(1);
+        assert_eq!(m.tag(), 1);
+        assert_eq!(*m, [1, 2, 3]);
+        let m = m.with_tag(2);
+        m.into_mut().push(4);
+        assert_eq!(v, [1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn tagged_box() {
+        let mut b = TaggedBox::<_, 3>::new(Box::new(vec![1u64]), 6);
+        b.push(2);
+        assert_eq!(b.tag(), 6);
+        let c = b.clone().with_tag(1);
+        assert_eq!((&**c, c.tag()), (&[1, 2][..], 1));
+        assert_eq!(*b.into_box(), [1, 2]);
+    }
+
+    #[test]
+    fn tagged_box_drop() {
+        let rc = Rc::new(());
+        let b = TaggedBox::<_, 3>::new(Box::new(rc.clone()), 1);
+        assert_eq!(Rc::strong_count(&rc), 2);
+        drop(b);
+        assert_eq!(Rc::strong_count(&rc), 1);
+        let b = TaggedBox::<_, 3>::new(Box::new(rc.clone()), 1);
+        let inner = b.into_box();
+        assert_eq!(Rc::strong_count(&rc), 2);
+        drop(inner);
+        assert_eq!(Rc::strong_count(&rc), 1);
+    }
+
+    #[test]
+    fn send_sync() {
+        fn send_sync<T: Send + Sync>() {}
+        send_sync::<TaggedRef<'_, u64, 3>>();
+        send_sync::<TaggedMut<'_, u64, 3>>();
+        send_sync::<TaggedBox<u64, 3>>();
+    }
+}